@@ -0,0 +1,86 @@
+use bmp::index::forward_index::{block_score, fwd2bfwd, ForwardIndexBuilder};
+use bmp::index::simd;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// Deterministic pseudo-random impacts in `1..=255`.
+fn impacts(len: usize, seed: u32) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| (i.wrapping_mul(2654435761).wrapping_add(seed) >> 24) as u8 | 1)
+        .collect()
+}
+
+fn bench_accumulate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("accumulate");
+    for bsize in [32, 64, 128, 256] {
+        let impacts = impacts(bsize, 7);
+        group.bench_with_input(BenchmarkId::new("scalar_u16", bsize), &bsize, |b, &n| {
+            let mut scores = vec![0u16; n];
+            b.iter(|| simd::accumulate_scalar(&mut scores, black_box(&impacts), 32))
+        });
+        group.bench_with_input(BenchmarkId::new("simd_u16", bsize), &bsize, |b, &n| {
+            let mut scores = vec![0u16; n];
+            b.iter(|| simd::accumulate_u16(&mut scores, black_box(&impacts), 32))
+        });
+        group.bench_with_input(BenchmarkId::new("scalar_u32", bsize), &bsize, |b, &n| {
+            let mut scores = vec![0u32; n];
+            b.iter(|| simd::accumulate_scalar(&mut scores, black_box(&impacts), 1000))
+        });
+        group.bench_with_input(BenchmarkId::new("simd_u32", bsize), &bsize, |b, &n| {
+            let mut scores = vec![0u32; n];
+            b.iter(|| simd::accumulate_u32(&mut scores, black_box(&impacts), 1000))
+        });
+    }
+    group.finish();
+}
+
+fn bench_skip_terms(c: &mut Criterion) {
+    let deltas: [u8; simd::SKIP_CHUNK] = std::array::from_fn(|i| (i * 5 % 17) as u8 + 1);
+    let headers: [u8; simd::SKIP_CHUNK] = std::array::from_fn(|i| (i * 3 % 11) as u8 * 2);
+    let mut group = c.benchmark_group("skip_terms");
+    group.bench_function("scalar", |b| {
+        b.iter(|| simd::skip_terms_scalar(black_box(&deltas), &headers, black_box(100), 64))
+    });
+    group.bench_function("simd", |b| {
+        b.iter(|| simd::skip_terms(black_box(&deltas), &headers, black_box(100), 64))
+    });
+    group.finish();
+}
+
+fn bench_block_score(c: &mut Criterion) {
+    let mut group = c.benchmark_group("block_score");
+    for bsize in [32, 64, 128] {
+        // Every document contains the 16 first terms, and a few of 2000 others.
+        let mut builder = ForwardIndexBuilder::new(0);
+        for doc in 0..bsize as u32 {
+            let scores = impacts(32, doc);
+            let vector = (0..32u32)
+                .map(|i| {
+                    let term = if i < 16 {
+                        i
+                    } else {
+                        16 + (doc * 97 + i * 61) % 2000
+                    };
+                    (term, scores[i as usize] as u32)
+                })
+                .collect();
+            builder.insert_document(vector);
+        }
+        let bfwd = fwd2bfwd(&builder.build(), bsize);
+        let query: Vec<(u32, u16)> = (0..2016).step_by(63).map(|t| (t, 17)).collect();
+        group.bench_with_input(BenchmarkId::new("u16", bsize), &bsize, |b, &n| {
+            b.iter(|| block_score::<u16>(black_box(&query), bfwd.block(0), n))
+        });
+        group.bench_with_input(BenchmarkId::new("u32", bsize), &bsize, |b, &n| {
+            b.iter(|| block_score::<u32>(black_box(&query), bfwd.block(0), n))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_accumulate,
+    bench_skip_terms,
+    bench_block_score
+);
+criterion_main!(benches);
@@ -0,0 +1,139 @@
+mod synthetic;
+
+use bmp::index::forward_index::{block_score, BlockForwardIndex};
+use bmp::index::inverted_index::Index;
+use bmp::index::posting_list::{PostingListIterator, SUPERBLOCK_SIZE};
+use bmp::query::cursor::{RangeMaxScore, RangeMaxScoreCursor};
+use bmp::query::cursors_from_weighted_query;
+use bmp::query::live_block::compute_upper_bounds;
+use bmp::query::quantizer::QueryQuantizer;
+use bmp::search::b_search_verbose;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use synthetic::{Corpus, CorpusConfig, QueryConfig};
+
+const BLOCK_SIZES: [usize; 3] = [8, 32, 128];
+
+/// Number of blocks scored by the `block_score` benchmark for each query.
+const SCORED_BLOCKS: usize = 64;
+
+struct Setup {
+    corpus: Corpus,
+    indexes: Vec<(Index, BlockForwardIndex)>,
+    queries: Vec<bmp::query::WeightedQuery>,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let corpus = Corpus::generate(CorpusConfig::from_env());
+        let queries = corpus.queries(&QueryConfig::default());
+        let indexes = BLOCK_SIZES
+            .iter()
+            .map(|&bsize| corpus.index(bsize, true))
+            .collect();
+        Setup {
+            corpus,
+            indexes,
+            queries,
+        }
+    }
+
+    fn cursors<'a>(&self, index: &'a Index) -> Vec<Vec<PostingListIterator<'a>>> {
+        let quantizer = QueryQuantizer::default();
+        self.queries
+            .iter()
+            .map(|query| cursors_from_weighted_query(query, index, &quantizer))
+            .collect()
+    }
+}
+
+fn query_vec(cursors: &[PostingListIterator]) -> Vec<(u32, u16)> {
+    let mut query: Vec<_> = cursors
+        .iter()
+        .map(|pl| (pl.term_id(), pl.term_weight()))
+        .collect();
+    query.sort_by_key(|e| e.0);
+    query
+}
+
+fn bench_synthetic(c: &mut Criterion) {
+    let setup = Setup::new();
+    eprintln!(
+        "{} documents, {} postings, {} queries",
+        setup.corpus.num_documents(),
+        setup.corpus.num_postings(),
+        setup.queries.len()
+    );
+
+    let mut group = c.benchmark_group("index_build");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(setup.corpus.num_postings() as u64));
+    for bsize in BLOCK_SIZES {
+        group.bench_with_input(BenchmarkId::from_parameter(bsize), &bsize, |b, &bsize| {
+            b.iter(|| setup.corpus.index(bsize, true))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("block_score");
+    group.throughput(Throughput::Elements(
+        (setup.queries.len() * SCORED_BLOCKS) as u64,
+    ));
+    for (bsize, (index, bfwd)) in BLOCK_SIZES.iter().zip(&setup.indexes) {
+        let queries: Vec<_> = setup.cursors(index).iter().map(|q| query_vec(q)).collect();
+        let step = (bfwd.num_blocks() / SCORED_BLOCKS).max(1);
+        group.bench_with_input(BenchmarkId::from_parameter(bsize), bsize, |b, &bsize| {
+            b.iter(|| {
+                for query in &queries {
+                    for block in (0..bfwd.num_blocks()).step_by(step).take(SCORED_BLOCKS) {
+                        black_box(block_score::<u16>(query, bfwd.block(block), bsize));
+                    }
+                }
+            })
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("upper_bounds");
+    group.throughput(Throughput::Elements(setup.queries.len() as u64));
+    for (bsize, (index, bfwd)) in BLOCK_SIZES.iter().zip(&setup.indexes) {
+        let queries = setup.cursors(index);
+        let live = vec![true; bfwd.num_blocks().div_ceil(SUPERBLOCK_SIZE)];
+        group.bench_with_input(BenchmarkId::from_parameter(bsize), bsize, |b, _| {
+            b.iter(|| {
+                for query in &queries {
+                    let ranges: Vec<_> = query
+                        .iter()
+                        .map(|pl| match pl.range_max_scores() {
+                            RangeMaxScore::Compressed(ranges) => ranges,
+                            RangeMaxScore::Raw(_) => unreachable!(),
+                        })
+                        .collect();
+                    let weights: Vec<_> = query.iter().map(|pl| pl.term_weight()).collect();
+                    black_box(compute_upper_bounds::<u16>(
+                        &ranges,
+                        &weights,
+                        bfwd.num_blocks(),
+                        &live,
+                    ));
+                }
+            })
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("query");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(setup.queries.len() as u64));
+    for (bsize, (index, bfwd)) in BLOCK_SIZES.iter().zip(&setup.indexes) {
+        let queries = setup.cursors(index);
+        for k in [10, 1000] {
+            group.bench_with_input(BenchmarkId::new(format!("k{}", k), bsize), bsize, |b, _| {
+                b.iter(|| b_search_verbose::<u16>(queries.clone(), bfwd, k, 1.0, 1.0, false))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_synthetic);
+criterion_main!(benches);
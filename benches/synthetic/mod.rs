@@ -0,0 +1,211 @@
+//! Synthetic collections and queries, so that benchmarks run without external datasets.
+
+use bmp::index::forward_index::{fwd2bfwd, BlockForwardIndex, ForwardIndexBuilder};
+use bmp::index::inverted_index::{Index, IndexBuilder};
+use bmp::query::WeightedQuery;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, LogNormal, Poisson, Zipf};
+use std::str::FromStr;
+
+/// Distribution of the impacts of the postings, mapped to `1..=255`.
+#[derive(Debug, Clone, Copy)]
+pub enum Impacts {
+    Uniform,
+    /// Skewed towards small impacts, like quantized learned sparse weights.
+    LogNormal {
+        mu: f64,
+        sigma: f64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct CorpusConfig {
+    pub num_documents: usize,
+    pub vocabulary: usize,
+    /// Exponent of the Zipfian distribution of the terms.
+    pub zipf_exponent: f64,
+    /// Mean number of distinct terms of a document.
+    pub document_length: f64,
+    pub impacts: Impacts,
+    pub seed: u64,
+}
+
+impl Default for CorpusConfig {
+    fn default() -> Self {
+        CorpusConfig {
+            num_documents: 50_000,
+            vocabulary: 30_000,
+            zipf_exponent: 1.0,
+            document_length: 60.0,
+            impacts: Impacts::LogNormal {
+                mu: 3.0,
+                sigma: 0.8,
+            },
+            seed: 42,
+        }
+    }
+}
+
+impl CorpusConfig {
+    /// Default configuration, overridden by the `BMP_BENCH_DOCUMENTS`, `BMP_BENCH_VOCABULARY`,
+    /// `BMP_BENCH_ZIPF`, `BMP_BENCH_DOCUMENT_LENGTH` and `BMP_BENCH_IMPACTS` (`uniform` or
+    /// `lognormal:<mu>:<sigma>`) environment variables.
+    pub fn from_env() -> Self {
+        let mut config = CorpusConfig::default();
+        if let Some(num_documents) = env("BMP_BENCH_DOCUMENTS") {
+            config.num_documents = num_documents;
+        }
+        if let Some(vocabulary) = env("BMP_BENCH_VOCABULARY") {
+            config.vocabulary = vocabulary;
+        }
+        if let Some(zipf_exponent) = env("BMP_BENCH_ZIPF") {
+            config.zipf_exponent = zipf_exponent;
+        }
+        if let Some(document_length) = env("BMP_BENCH_DOCUMENT_LENGTH") {
+            config.document_length = document_length;
+        }
+        if let Some(impacts) = env::<String>("BMP_BENCH_IMPACTS") {
+            config.impacts = match impacts.split(':').collect::<Vec<_>>()[..] {
+                ["uniform"] => Impacts::Uniform,
+                ["lognormal", mu, sigma] => Impacts::LogNormal {
+                    mu: mu.parse().expect("invalid mu"),
+                    sigma: sigma.parse().expect("invalid sigma"),
+                },
+                _ => panic!("invalid BMP_BENCH_IMPACTS: {}", impacts),
+            };
+        }
+        config
+    }
+}
+
+fn env<T: FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    Some(
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("invalid {}: {}", name, value)),
+    )
+}
+
+/// Queries made of a few terms of a document, expanded with weaker terms drawn from the whole
+/// vocabulary, like learned sparse queries.
+#[derive(Debug, Clone)]
+pub struct QueryConfig {
+    pub num_queries: usize,
+    /// Mean number of terms taken from a document.
+    pub query_length: f64,
+    /// Mean number of expansion terms.
+    pub expansion_length: f64,
+    pub seed: u64,
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        QueryConfig {
+            num_queries: 100,
+            query_length: 4.0,
+            expansion_length: 20.0,
+            seed: 7,
+        }
+    }
+}
+
+pub struct Corpus {
+    config: CorpusConfig,
+    /// `(term id, impact)` pairs of each document, by increasing term id.
+    documents: Vec<Vec<(u32, u32)>>,
+}
+
+fn term(term_id: u32) -> String {
+    format!("t{}", term_id)
+}
+
+fn sample_count(rng: &mut StdRng, mean: f64) -> usize {
+    Poisson::new(mean).unwrap().sample(rng) as usize
+}
+
+impl Corpus {
+    pub fn generate(config: CorpusConfig) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let terms = Zipf::new(config.vocabulary as u64, config.zipf_exponent).unwrap();
+        let log_normal = match config.impacts {
+            Impacts::LogNormal { mu, sigma } => Some(LogNormal::new(mu, sigma).unwrap()),
+            Impacts::Uniform => None,
+        };
+        let documents = (0..config.num_documents)
+            .map(|_| {
+                let length = sample_count(&mut rng, config.document_length).max(1);
+                let mut term_ids: Vec<u32> = (0..length)
+                    .map(|_| terms.sample(&mut rng) as u32 - 1)
+                    .collect();
+                term_ids.sort_unstable();
+                term_ids.dedup();
+                term_ids
+                    .into_iter()
+                    .map(|term_id| {
+                        let impact = match log_normal {
+                            Some(log_normal) => {
+                                log_normal.sample(&mut rng).clamp(1.0, 255.0) as u32
+                            }
+                            None => rng.gen_range(1..=255),
+                        };
+                        (term_id, impact)
+                    })
+                    .collect()
+            })
+            .collect();
+        Corpus { config, documents }
+    }
+
+    pub fn num_documents(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn num_postings(&self) -> usize {
+        self.documents.iter().map(Vec::len).sum()
+    }
+
+    /// Builds the index of the corpus, with blocks of `bsize` documents.
+    pub fn index(&self, bsize: usize, compress_range: bool) -> (Index, BlockForwardIndex) {
+        let mut builder = IndexBuilder::new(self.documents.len(), bsize);
+        let mut fwd_builder = ForwardIndexBuilder::new(0);
+        for term_id in 0..self.config.vocabulary as u32 {
+            builder.insert_term(&term(term_id), Vec::new());
+        }
+        for (doc_id, document) in self.documents.iter().enumerate() {
+            builder.insert_document(&format!("d{}", doc_id));
+            for &(term_id, impact) in document {
+                builder.push_posting(term_id, doc_id as u32, impact);
+            }
+            fwd_builder.insert_document(document.clone());
+        }
+        (
+            builder.build(compress_range),
+            fwd2bfwd(&fwd_builder.build(), bsize),
+        )
+    }
+
+    pub fn queries(&self, config: &QueryConfig) -> Vec<WeightedQuery> {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let terms = Zipf::new(self.config.vocabulary as u64, self.config.zipf_exponent).unwrap();
+        (0..config.num_queries)
+            .map(|_| {
+                let mut query = WeightedQuery::new();
+                let document = &self.documents[rng.gen_range(0..self.documents.len())];
+                let length = sample_count(&mut rng, config.query_length).clamp(1, document.len());
+                for &(term_id, _) in document.choose_multiple(&mut rng, length) {
+                    query.insert(term(term_id), rng.gen_range(1.0..3.0));
+                }
+                for _ in 0..sample_count(&mut rng, config.expansion_length) {
+                    let term_id = terms.sample(&mut rng) as u32 - 1;
+                    query
+                        .entry(term(term_id))
+                        .or_insert_with(|| rng.gen_range(0.05..1.0));
+                }
+                query
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,132 @@
+use bmp::index::posting_list::{CompressedRangeMaxes, SuperblockMaxes, SUPERBLOCK_SIZE};
+use bmp::query::live_block::{
+    compute_superblock_upper_bounds, compute_upper_bounds, compute_upper_bounds_raw,
+};
+use bmp::query::score::Score;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const NUM_BLOCKS: usize = 1 << 20;
+const NUM_SUPERBLOCKS: usize = NUM_BLOCKS / SUPERBLOCK_SIZE;
+
+/// Range maxes of a term appearing in about `1 / sparsity` of the blocks.
+fn range_maxes(sparsity: u32, seed: u32) -> Vec<u8> {
+    (0..NUM_BLOCKS as u32)
+        .map(|i| {
+            let hash = i.wrapping_add(seed).wrapping_mul(2654435761) >> 8;
+            if hash % sparsity == 0 {
+                (hash >> 16) as u8 | 1
+            } else {
+                0
+            }
+        })
+        .collect()
+}
+
+/// Term-at-a-time scalar loop, for reference.
+fn upper_bounds_scalar<S: Score>(query_ranges: &[&[u8]], query_weights: &[u16]) -> Vec<S> {
+    let mut upper_bounds = vec![S::default(); NUM_BLOCKS];
+    for (&ranges, &weight) in query_ranges.iter().zip(query_weights) {
+        for (ub, &value) in upper_bounds.iter_mut().zip(ranges) {
+            *ub = ub.saturating_add(S::product(weight, value));
+        }
+    }
+    upper_bounds
+}
+
+fn bench_upper_bounds(c: &mut Criterion) {
+    let mut group = c.benchmark_group("upper_bounds");
+    group.sample_size(20);
+    for sparsity in [2, 100] {
+        let ranges: Vec<Vec<u8>> = (0..16).map(|t| range_maxes(sparsity, t)).collect();
+        let raw: Vec<&[u8]> = ranges.iter().map(Vec::as_slice).collect();
+        let compressed: Vec<_> = ranges
+            .iter()
+            .map(|r| CompressedRangeMaxes::new(r))
+            .collect();
+        let compressed: Vec<_> = compressed.iter().collect();
+        let weights: Vec<u16> = (1..=16).collect();
+        let live = vec![true; NUM_SUPERBLOCKS];
+
+        group.bench_with_input(BenchmarkId::new("scalar", sparsity), &sparsity, |b, _| {
+            b.iter(|| upper_bounds_scalar::<u16>(black_box(&raw), &weights))
+        });
+        group.bench_with_input(BenchmarkId::new("raw", sparsity), &sparsity, |b, _| {
+            b.iter(|| compute_upper_bounds_raw::<u16>(black_box(&raw), &weights, NUM_BLOCKS, &live))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("compressed", sparsity),
+            &sparsity,
+            |b, _| {
+                b.iter(|| {
+                    compute_upper_bounds::<u16>(black_box(&compressed), &weights, NUM_BLOCKS, &live)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Upper bounds of a query whose terms are concentrated in a few superblocks, e.g. because of
+/// document reordering, computed with and without discarding superblocks first.
+fn bench_superblocks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("superblocks");
+    group.sample_size(20);
+    // Each term appears with high impacts in every other block of one superblock out of 64, and
+    // sparsely with low impacts elsewhere, except for a few frequent terms with low impacts in
+    // every block.
+    let ranges: Vec<Vec<u8>> = (0..16)
+        .map(|t| {
+            if t < 4 {
+                return (0..NUM_BLOCKS).map(|b| (b % 16) as u8 + 1).collect();
+            }
+            let mut ranges = range_maxes(1000, t);
+            for (id, superblock) in ranges.chunks_mut(SUPERBLOCK_SIZE).enumerate() {
+                if (id + t as usize).is_multiple_of(64) {
+                    superblock.iter_mut().step_by(2).for_each(|v| *v = 200);
+                } else {
+                    superblock.iter_mut().for_each(|v| *v /= 4);
+                }
+            }
+            ranges
+        })
+        .collect();
+    let raw: Vec<&[u8]> = ranges.iter().map(Vec::as_slice).collect();
+    let compressed: Vec<_> = ranges
+        .iter()
+        .map(|r| CompressedRangeMaxes::new(r))
+        .collect();
+    let compressed: Vec<_> = compressed.iter().collect();
+    let maxes: Vec<_> = ranges.iter().map(|r| SuperblockMaxes::new(r)).collect();
+    let maxes: Vec<_> = maxes.iter().collect();
+    let weights: Vec<u16> = vec![10; 16];
+    let all = vec![true; NUM_SUPERBLOCKS];
+    let live = |maxes: &[&SuperblockMaxes]| -> Vec<bool> {
+        compute_superblock_upper_bounds::<u16>(maxes, &weights, NUM_SUPERBLOCKS)
+            .into_iter()
+            .map(|ub| ub > 2600)
+            .collect()
+    };
+
+    group.bench_function("raw/all", |b| {
+        b.iter(|| compute_upper_bounds_raw::<u16>(black_box(&raw), &weights, NUM_BLOCKS, &all))
+    });
+    group.bench_function("raw/pruned", |b| {
+        b.iter(|| {
+            let live = live(black_box(&maxes));
+            compute_upper_bounds_raw::<u16>(&raw, &weights, NUM_BLOCKS, &live)
+        })
+    });
+    group.bench_function("compressed/all", |b| {
+        b.iter(|| compute_upper_bounds::<u16>(black_box(&compressed), &weights, NUM_BLOCKS, &all))
+    });
+    group.bench_function("compressed/pruned", |b| {
+        b.iter(|| {
+            let live = live(black_box(&maxes));
+            compute_upper_bounds::<u16>(&compressed, &weights, NUM_BLOCKS, &live)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_upper_bounds, bench_superblocks);
+criterion_main!(benches);
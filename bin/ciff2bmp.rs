@@ -1,3 +1,4 @@
+use bmp::index::pruning::StaticPruningArgs;
 use bmp::CiffToBmp;
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -17,11 +18,16 @@ struct Args {
     bsize: usize,
     #[structopt(short, long, help = "Compress range data")]
     compress_range: bool,
+    #[structopt(flatten)]
+    pruning: StaticPruningArgs,
+    #[structopt(long, help = "Only log warnings and errors, without progress bars")]
+    quiet: bool,
 }
 
 fn main() {
     // Parse command-line arguments
     let args = Args::from_args();
+    bmp::util::init_logger(args.quiet);
 
     // Create a CiffToBmp converter with default settings
     let mut converter = CiffToBmp::default();
@@ -31,6 +37,8 @@ fn main() {
         .input_path(args.ciff_file)
         .output_path(args.output)
         .compress_range(args.compress_range)
+        .static_pruning(args.pruning.pruning())
+        .quiet(args.quiet)
         .bsize(args.bsize);
 
     // Convert the Ciff file to BMP format
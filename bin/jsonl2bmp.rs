@@ -0,0 +1,65 @@
+use bmp::index::pruning::StaticPruningArgs;
+use bmp::JsonlToBmp;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// Struct for command-line arguments
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "jsonl2bmp",
+    about = "Generates a BMP index from JSONL files of {\"id\": ..., \"vector\": {term: weight}} documents"
+)]
+struct Args {
+    #[structopt(
+        short,
+        long,
+        required = true,
+        help = "Paths to JSONL files, read in order (gzip-compressed if ending in .gz)"
+    )]
+    input: Vec<PathBuf>,
+    #[structopt(short, long, help = "Output filename")]
+    output: PathBuf,
+    #[structopt(short, long, help = "Block size")]
+    bsize: usize,
+    #[structopt(short, long, help = "Compress range data")]
+    compress_range: bool,
+    #[structopt(
+        long,
+        help = "Multiply weights by this factor and truncate them into impacts (default: map the largest weight to 255)"
+    )]
+    scale: Option<f32>,
+    #[structopt(flatten)]
+    pruning: StaticPruningArgs,
+    #[structopt(
+        long,
+        help = "Store the fields of the documents other than id and vector as their metadata"
+    )]
+    metadata: bool,
+    #[structopt(long, help = "Only log warnings and errors, without progress bars")]
+    quiet: bool,
+}
+
+fn main() {
+    let args = Args::from_args();
+    bmp::util::init_logger(args.quiet);
+
+    let mut converter = JsonlToBmp::default();
+    for input in args.input {
+        converter.input_path(input);
+    }
+    converter
+        .output_path(args.output)
+        .compress_range(args.compress_range)
+        .static_pruning(args.pruning.pruning())
+        .metadata(args.metadata)
+        .quiet(args.quiet)
+        .bsize(args.bsize);
+    if let Some(scale) = args.scale {
+        converter.scale(scale);
+    }
+
+    if let Err(error) = converter.to_bmp() {
+        eprintln!("ERROR: {:#}", error);
+        std::process::exit(1);
+    }
+}
@@ -1,7 +1,11 @@
 use anyhow::Result;
 
-use bmp::query::cursors_from_queries;
-use bmp::search::b_search;
+use bmp::query::expansion::TermExpansion;
+use bmp::query::pruning::QueryPruning;
+use bmp::query::quantizer::{QueryQuantizer, Rounding, Scaling};
+use bmp::query::score::needs_wide_scores;
+use bmp::query::{cursors_from_quantized_query, weighted_queries_from_file, QueryFormat};
+use bmp::search::b_search_verbose;
 use bmp::util::to_trec;
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -10,12 +14,18 @@ use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "search", about = "Search an index and produce a TREC output")]
-
 struct Args {
     #[structopt(short, long, help = "Path to the index")]
     index: PathBuf,
     #[structopt(short, long, help = "Path to the queries")]
     queries: PathBuf,
+    #[structopt(
+        short,
+        long,
+        help = "Format of the queries: pisa, jsonl or tsv",
+        default_value = "pisa"
+    )]
+    format: QueryFormat,
     #[structopt(short, long, help = "Number of documents to retrieve")]
     k: usize,
     #[structopt(short, long, help = "approximation factor", default_value = "1.0")]
@@ -27,23 +37,86 @@ struct Args {
         default_value = "1.0"
     )]
     beta: f32,
+    #[structopt(
+        long,
+        help = "Maximum quantized query weight, up to 65535",
+        default_value = "32"
+    )]
+    max_weight: u16,
+    #[structopt(
+        long,
+        help = "Rounding of query weights: ceil, round or floor",
+        default_value = "ceil"
+    )]
+    rounding: Rounding,
+    #[structopt(
+        long,
+        help = "Scaling of query weights: per-query, downscale-only, global or global:<weight> [default: downscale-only for pisa queries, per-query otherwise]"
+    )]
+    scaling: Option<Scaling>,
+    #[structopt(
+        long,
+        help = "Query terms pruning, applied before beta: top-fraction:<f>, max-terms:<n>, weight-mass:<f>, contribution-mass:<f> or max-df:<f>"
+    )]
+    pruning: Option<QueryPruning>,
+    #[structopt(
+        long,
+        help = "Expand query terms written as patterns (prefix*, /regex/, fuzzy~, fuzzy~<distance> or [lower TO upper]) into at most this number of index terms each"
+    )]
+    max_expansions: Option<usize>,
+    #[structopt(long, help = "Only log warnings and errors, without progress bars")]
+    quiet: bool,
 }
 fn main() -> Result<()> {
     let args = Args::from_args();
+    bmp::util::init_logger(args.quiet);
 
     // 1. Load the index
-    eprintln!("Loading the index");
-    let (index, bfwd) = bmp::index::from_file(args.index)?;
+    log::info!("Loading the index");
+    let (index, bfwd) = bmp::index::BmpIndex::open(args.index)?.into_parts();
 
     // 2. Load the queries
-    eprintln!("Loading the queries");
-    let (q_ids, cursors) = cursors_from_queries(args.queries, &index);
+    log::info!("Loading the queries");
+    let quantizer = QueryQuantizer::new(
+        args.max_weight,
+        args.rounding,
+        args.scaling
+            .unwrap_or_else(|| args.format.default_scaling()),
+    );
+    let (q_ids, mut queries) = weighted_queries_from_file(args.queries, args.format)?;
+    if let Some(max_expansions) = args.max_expansions {
+        let expansion = TermExpansion::new(max_expansions);
+        queries = queries
+            .iter()
+            .map(|query| expansion.expand_query(query, &index))
+            .collect::<Result<_>>()?;
+    }
+    let mut cursors: Vec<_> = quantizer
+        .quantize_all(&queries)
+        .iter()
+        .map(|query| cursors_from_quantized_query(query, &index))
+        .collect();
+    if let Some(pruning) = args.pruning {
+        for query in cursors.iter_mut() {
+            pruning.prune(query, index.num_documents());
+        }
+    }
 
-    eprintln!("Performing query processing");
-    let results = b_search(cursors, &bfwd, args.k, args.alpha, args.beta);
+    // 3. Queries whose scores may not fit in 16 bits need 32-bit scores
+    log::info!("Performing query processing");
+    let verbose = !args.quiet;
+    let run = if cursors.iter().any(|query| needs_wide_scores(query)) {
+        let results =
+            b_search_verbose::<u32>(cursors, &bfwd, args.k, args.alpha, args.beta, verbose);
+        to_trec(&q_ids, results, index.documents())
+    } else {
+        let results =
+            b_search_verbose::<u16>(cursors, &bfwd, args.k, args.alpha, args.beta, verbose);
+        to_trec(&q_ids, results, index.documents())
+    };
 
-    eprintln!("Exporting TREC run");
+    log::info!("Exporting TREC run");
     // 4. Log results into TREC format
-    print!("{}", to_trec(&q_ids, results, index.documents()));
+    print!("{}", run);
     Ok(())
 }
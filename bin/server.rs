@@ -0,0 +1,55 @@
+use anyhow::Result;
+use bmp::server::BmpSearchService;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use tonic::transport::Server;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "server",
+    about = "Serve an index through the gRPC search service"
+)]
+struct Args {
+    #[structopt(short, long, help = "Path to the index")]
+    index: PathBuf,
+    #[structopt(
+        short,
+        long,
+        help = "Address to listen on",
+        default_value = "0.0.0.0:50051"
+    )]
+    addr: SocketAddr,
+    #[structopt(
+        long,
+        help = "Enables the expansion of query term patterns, into at most this number of terms each"
+    )]
+    max_expansions: Option<usize>,
+    #[structopt(
+        long,
+        help = "Largest number of documents a request can retrieve",
+        default_value = "10000"
+    )]
+    max_k: usize,
+    #[structopt(long, help = "Only log warnings and errors")]
+    quiet: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::from_args();
+    bmp::util::init_logger(args.quiet);
+
+    log::info!("Loading the index");
+    let mut service = BmpSearchService::from_file(args.index)?.with_max_k(args.max_k);
+    if let Some(max_expansions) = args.max_expansions {
+        service = service.with_max_expansions(max_expansions);
+    }
+
+    log::info!("Listening on {}", args.addr);
+    Server::builder()
+        .add_service(service.into_server())
+        .serve(args.addr)
+        .await?;
+    Ok(())
+}
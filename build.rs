@@ -1,30 +1,56 @@
-//! Here, we generate Rust code from a proto file before project compilation.
+//! Here, we generate Rust code from the proto files before project compilation.
 use std::env;
 use std::fs::{read_to_string, File};
 use std::io::{BufWriter, Write};
 use std::path::Path;
 
+const PROTO_FILES: [&str; 2] = ["common-index-format-v1", "bmp-search-v1"];
+
 fn main() {
     let out_dir_env = env::var_os("OUT_DIR").unwrap();
     let out_dir = Path::new(&out_dir_env);
     protobuf_codegen_pure::Codegen::new()
         .out_dir(out_dir)
-        .inputs(["proto/common-index-format-v1.proto"])
+        .inputs(PROTO_FILES.map(|name| format!("proto/{}.proto", name)))
         .include("proto")
         .run()
         .expect("Codegen failed.");
-    let path = out_dir.join("common_index_format_v1.rs");
-    let code = read_to_string(&path).expect("Failed to read generated file");
-    let mut writer = BufWriter::new(File::create(path).unwrap());
-    for line in code.lines() {
-        if !line.contains("//!") && !line.contains("#!") {
-            writer
-                .write_all(line.as_bytes())
-                .expect("Failed to write to generated file");
-            writer
-                .write_all(&[b'\n'])
-                .expect("Failed to write to generated file");
+    for name in PROTO_FILES {
+        let path = out_dir.join(format!("{}.rs", name.replace('-', "_")));
+        let code = read_to_string(&path).expect("Failed to read generated file");
+        let mut writer = BufWriter::new(File::create(path).unwrap());
+        for line in code.lines() {
+            if !line.contains("//!") && !line.contains("#!") {
+                writer
+                    .write_all(line.as_bytes())
+                    .expect("Failed to write to generated file");
+                writer
+                    .write_all(b"\n")
+                    .expect("Failed to write to generated file");
+            }
         }
     }
+
+    // The messages of the search service are generated above by `protobuf_codegen_pure`, which
+    // does not support services, so the gRPC stubs are defined here and use a codec wrapping
+    // `protobuf::Message`.
+    let service = tonic_build::manual::Service::builder()
+        .name("SearchService")
+        .package("bmp")
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("search")
+                .route_name("Search")
+                .input_type("crate::server::SearchRequest")
+                .output_type("crate::server::SearchResponse")
+                .codec_path("crate::server::ProtobufCodec")
+                .build(),
+        )
+        .build();
+    tonic_build::manual::Builder::new()
+        .out_dir(out_dir)
+        .compile(&[service]);
+
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=proto");
 }
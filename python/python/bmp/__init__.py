@@ -1 +1 @@
-from bmp._bmp import ciff2bmp, search, Searcher, InvertedIndexer, Indexer
+from bmp._bmp import ciff2bmp, jsonl2bmp, search, Searcher, InvertedIndexer, Indexer, BmpError
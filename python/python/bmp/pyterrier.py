@@ -57,17 +57,18 @@ class BmpIndexer(pt.Indexer):
         return self
 
 class BmpRetriever(pt.Indexer):
-    def __init__(self, bmp_index, *, num_results=1000, alpha=1.0, beta=1.0):
+    def __init__(self, bmp_index, *, num_results=1000, alpha=1.0, beta=1.0, threads=None):
         self.bmp_index = bmp_index
         self.num_results = num_results
         self.alpha = alpha
         self.beta = beta
+        self.threads = threads
     def transform(self, inp):
         pta.validate.query_frame(inp, extra_columns=['query_toks'])
         searcher = self.bmp_index.load_into_memory()
         res = pta.DataFrameBuilder(['docno', 'score', 'rank'])
-        for toks in inp['query_toks']:
-            docnos, scores = searcher.search(toks, k=self.num_results, alpha=self.alpha, beta=self.beta)
+        results = searcher.search_batch(list(inp['query_toks']), k=self.num_results, alpha=self.alpha, beta=self.beta, threads=self.threads)
+        for docnos, scores in results:
             res.extend({
                 'docno': docnos,
                 'score': scores,
@@ -76,4 +77,4 @@ class BmpRetriever(pt.Indexer):
         return res.to_df(inp)
     def fuse_rank_cutoff(self, k):
         if self.num_results > k:
-            return BmpRetriever(self.bmp_index, num_results=k, alpha=self.alpha, beta=self.beta)
+            return BmpRetriever(self.bmp_index, num_results=k, alpha=self.alpha, beta=self.beta, threads=self.threads)
@@ -1,127 +1,610 @@
+use bmp::index::posting_list::PostingListIterator;
 use bmp::query::cursors_from_queries;
-use bmp::query::MAX_TERM_WEIGHT;
+use bmp::query::{cursors_from_quantized_query, cursors_from_weighted_query};
+use bmp::query::expansion::{TermExpansion, TermPattern, DEFAULT_MAX_EXPANSIONS};
+use bmp::query::pruning::QueryPruning;
+use bmp::query::quantizer::{QueryQuantizer, Rounding, Scaling};
+use bmp::query::score::{needs_wide_scores, Score};
 use bmp::search::b_search_verbose;
 use bmp::util::to_trec;
-use bmp::CiffToBmp;
+use bmp::index::pruning::StaticPruning;
+use bmp::index::quantize_impact;
+use bmp::index::writer::IndexWriter;
+use bmp::index::metadata::DocumentMetadata;
+use bmp::index::BmpIndex;
+use bmp::{CiffToBmp, JsonlToBmp};
+use pyo3::buffer::{Element, PyBuffer};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyDeprecationWarning, PyException, PyFileNotFoundError, PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple};
+use rayon::prelude::*;
 use std::path::PathBuf;
 use std::collections::HashMap;
-use bmp::index::posting_list::PostingListIterator;
+use std::sync::{Arc, Mutex, OnceLock};
+
+create_exception!(bmp, BmpError, PyException, "Error raised when indexing or searching fails.");
+
+/// Converts an error of the library into `FileNotFoundError` when caused by a missing file, and
+/// into `BmpError` otherwise, with the whole chain of causes as message.
+fn bmp_error(error: anyhow::Error) -> PyErr {
+    let message = format!("{:#}", error);
+    let not_found = error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|cause| cause.kind() == std::io::ErrorKind::NotFound);
+    if not_found {
+        PyFileNotFoundError::new_err(message)
+    } else {
+        BmpError::new_err(message)
+    }
+}
+
+/// Forwards the records of the `log` crate to the `bmp` logger of the Python `logging` module, so
+/// that they can be shown, silenced or redirected like those of Python libraries.
+struct PythonLogger;
+
+impl log::Log for PythonLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let level = match record.level() {
+            log::Level::Error => 40,
+            log::Level::Warn => 30,
+            log::Level::Info => 20,
+            log::Level::Debug => 10,
+            log::Level::Trace => 5,
+        };
+        Python::with_gil(|py| {
+            // Logging must not fail the operation being logged.
+            let _ = py
+                .import_bound("logging")
+                .and_then(|logging| logging.call_method1("getLogger", ("bmp",)))
+                .and_then(|logger| logger.call_method1("log", (level, record.args().to_string())));
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+fn check_bsize(bsize: usize) -> PyResult<()> {
+    if bsize == 0 {
+        return Err(PyValueError::new_err("bsize must be positive"));
+    }
+    Ok(())
+}
+
+fn check_search_params(alpha: f32, beta: f32) -> PyResult<()> {
+    if alpha.is_nan() || alpha <= 0.0 {
+        return Err(PyValueError::new_err("alpha must be positive"));
+    }
+    if !(beta > 0.0 && beta <= 1.0) {
+        return Err(PyValueError::new_err("beta must be in (0, 1]"));
+    }
+    Ok(())
+}
+
+fn static_pruning(
+    min_impact: Option<u32>,
+    posting_list_fraction: Option<f32>,
+    terms_per_document: Option<usize>,
+) -> PyResult<StaticPruning> {
+    let mut pruning = StaticPruning::default();
+    if let Some(min_impact) = min_impact {
+        pruning.min_impact(min_impact);
+    }
+    if let Some(fraction) = posting_list_fraction {
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(PyValueError::new_err("posting_list_fraction must be in [0, 1]"));
+        }
+        pruning.posting_list_fraction(fraction);
+    }
+    if let Some(terms) = terms_per_document {
+        pruning.terms_per_document(terms);
+    }
+    Ok(pruning)
+}
 
 #[pyfunction]
-fn ciff2bmp(ciff_file: PathBuf, output: PathBuf, bsize: usize, compress_range: bool) {
+#[pyo3(signature = (ciff_file, output, bsize, compress_range, min_impact=None, posting_list_fraction=None, terms_per_document=None, quiet=false))]
+#[allow(clippy::too_many_arguments)]
+fn ciff2bmp(
+    py: Python<'_>,
+    ciff_file: PathBuf,
+    output: PathBuf,
+    bsize: usize,
+    compress_range: bool,
+    min_impact: Option<u32>,
+    posting_list_fraction: Option<f32>,
+    terms_per_document: Option<usize>,
+    quiet: bool,
+) -> PyResult<()> {
+    check_bsize(bsize)?;
+    let pruning = static_pruning(min_impact, posting_list_fraction, terms_per_document)?;
     let mut converter = CiffToBmp::default();
     converter
         .input_path(ciff_file)
         .output_path(output)
         .compress_range(compress_range)
+        .static_pruning(pruning)
+        .quiet(quiet)
+        .bsize(bsize);
+    py.allow_threads(|| converter.to_bmp()).map_err(bmp_error)
+}
+
+/// Builds an index from JSONL files of `{"id": ..., "vector": {term: weight}}` documents,
+/// gzip-compressed if ending in `.gz`. Weights are multiplied by `scale` and truncated into
+/// impacts; by default, the largest weight becomes 255.
+#[pyfunction]
+#[pyo3(signature = (jsonl_files, output, bsize, compress_range, scale=None, min_impact=None, posting_list_fraction=None, terms_per_document=None, metadata=false, quiet=false))]
+#[allow(clippy::too_many_arguments)]
+fn jsonl2bmp(
+    py: Python<'_>,
+    jsonl_files: Vec<PathBuf>,
+    output: PathBuf,
+    bsize: usize,
+    compress_range: bool,
+    scale: Option<f32>,
+    min_impact: Option<u32>,
+    posting_list_fraction: Option<f32>,
+    terms_per_document: Option<usize>,
+    metadata: bool,
+    quiet: bool,
+) -> PyResult<()> {
+    check_bsize(bsize)?;
+    if jsonl_files.is_empty() {
+        return Err(PyValueError::new_err("jsonl_files must not be empty"));
+    }
+    if scale.is_some_and(|scale| !(scale.is_finite() && scale > 0.0)) {
+        return Err(PyValueError::new_err("scale must be positive"));
+    }
+    let pruning = static_pruning(min_impact, posting_list_fraction, terms_per_document)?;
+    let mut converter = JsonlToBmp::default();
+    for jsonl_file in jsonl_files {
+        converter.input_path(jsonl_file);
+    }
+    converter
+        .output_path(output)
+        .compress_range(compress_range)
+        .static_pruning(pruning)
+        .metadata(metadata)
+        .quiet(quiet)
         .bsize(bsize);
-    if let Err(error) = converter.to_bmp() {
-        eprintln!("ERROR: {}", error);
-        std::process::exit(1);
+    if let Some(scale) = scale {
+        converter.scale(scale);
     }
+    py.allow_threads(|| converter.to_bmp()).map_err(bmp_error)
+}
+
+/// Thread pools of the batch searches with a given number of threads, kept across calls since
+/// creating one spawns its threads. Searches without a number of threads use the global pool.
+static THREAD_POOLS: OnceLock<Mutex<HashMap<usize, Arc<rayon::ThreadPool>>>> = OnceLock::new();
+
+fn thread_pool(threads: usize) -> PyResult<Arc<rayon::ThreadPool>> {
+    let mut pools = THREAD_POOLS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(pool) = pools.get(&threads) {
+        return Ok(Arc::clone(pool));
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
+    let pool = Arc::new(pool);
+    pools.insert(threads, Arc::clone(&pool));
+    Ok(pool)
 }
 
 #[pyclass]
 struct Searcher {
     index: bmp::index::inverted_index::Index,
     bfwd: bmp::index::forward_index::BlockForwardIndex,
+    metadata: Option<DocumentMetadata>,
 }
 
+/// Doc numbers and scores of the documents retrieved for a query.
+type Hits = Vec<(u32, f32)>;
+
 #[pymethods]
 impl Searcher {
 
     #[new]
     fn py_new(path: PathBuf) -> PyResult<Self> {
-        let (index, bfwd) = bmp::index::from_file(path).expect("Index cannot be loaded.");
-        Ok(Searcher {index: index, bfwd: bfwd})
+        let mut index = BmpIndex::open(path).map_err(bmp_error)?;
+        let metadata = index.take_metadata();
+        let (index, bfwd) = index.into_parts();
+        Ok(Searcher {index, bfwd, metadata})
+    }
+
+    /// Number of documents in the index.
+    #[getter]
+    fn num_docs(&self) -> usize {
+        self.index.num_documents()
+    }
+
+    /// Terms of the index, by term id.
+    #[getter]
+    fn vocab(&self) -> Vec<String> {
+        self.index.terms()
+    }
+
+    /// Number of documents per block.
+    #[getter]
+    fn block_size(&self) -> usize {
+        self.bfwd.block_size
+    }
+
+    #[getter]
+    fn num_blocks(&self) -> usize {
+        self.bfwd.num_blocks()
+    }
+
+    /// Whether the block upper bounds are compressed.
+    #[getter]
+    fn compress_range(&self) -> bool {
+        self.index.compressed_ranges()
+    }
+
+    /// Document frequency and largest impact of `term`, or `None` if it is not in the index.
+    fn term_stats(&self, term: &str) -> Option<(u32, u8)> {
+        self.index
+            .posting_list(term)
+            .map(|posting_list| (posting_list.doc_freq(), posting_list.max_impact()))
+    }
+
+    /// IDs of the documents, by document number.
+    #[getter]
+    fn doc_ids(&self) -> Vec<String> {
+        self.index.documents().iter().collect()
+    }
+
+    /// Document number of the document `doc_id`, or `None` if it is not in the index.
+    fn doc_number(&self, doc_id: &str) -> Option<u32> {
+        self.index.documents().id(doc_id)
+    }
+
+    /// ID of the document numbered `doc_number`, or `None` if there is no such document.
+    fn doc_id(&self, doc_number: u32) -> Option<String> {
+        self.index.documents().get(doc_number)
+    }
+
+    /// Terms of the index matching `pattern`: `prefix*`, `/regex/` (matching whole terms),
+    /// `fuzzy~` or `fuzzy~<distance>` (Levenshtein distance, 1 by default), `[lower TO upper]`
+    /// (lexicographic range, with bounds excluded by curly brackets), or a plain term. At most
+    /// `max_expansions` terms are returned, those with the largest maximum impact first.
+    #[pyo3(signature = (pattern, max_expansions=DEFAULT_MAX_EXPANSIONS))]
+    fn expand_term(&self, pattern: &str, max_expansions: usize) -> PyResult<Vec<String>> {
+        TermExpansion::new(max_expansions)
+            .expand(&TermPattern::parse(pattern), &self.index)
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))
     }
 
+    /// Whether the index stores document metadata.
+    #[getter]
+    fn has_metadata(&self) -> bool {
+        self.metadata.is_some()
+    }
+
+    /// Metadata of the documents `doc_ids`, e.g. of search results: a dict, or `None` for the
+    /// documents without metadata or not in the index.
+    fn metadata(&self, py: Python<'_>, doc_ids: Vec<String>) -> PyResult<Vec<PyObject>> {
+        let loads = py.import_bound("json")?.getattr("loads")?;
+        let doc_lexicon = self.index.documents();
+        doc_ids
+            .iter()
+            .map(|doc_id| {
+                let metadata = doc_lexicon.id(doc_id).and_then(|doc_id| {
+                    self.metadata.as_ref().and_then(|metadata| metadata.get(doc_id))
+                });
+                match metadata {
+                    Some(metadata) => Ok(loads.call1((metadata,))?.unbind()),
+                    None => Ok(py.None()),
+                }
+            })
+            .collect()
+    }
+
+    /// Searches a query. Returns the doc IDs and scores of the results. With `max_expansions`,
+    /// query terms written as patterns (see `expand_term`) are expanded.
+    #[pyo3(signature = (query, k, alpha, beta, max_weight=32, rounding="ceil", scaling="per-query", pruning=None, max_expansions=None))]
+    #[allow(clippy::too_many_arguments)]
     fn search(
         &self,
         query: HashMap<String, f32>,
         k: usize,
         alpha: f32,
         beta: f32,
+        max_weight: u16,
+        rounding: &str,
+        scaling: &str,
+        pruning: Option<&str>,
+        max_expansions: Option<usize>,
     ) -> PyResult<(Vec<String>, Vec<f32>)> {
-        let max_tok_weight = query.iter().map(|p| *p.1).filter(|&value| !value.is_nan()).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
-        let mut quant_query: HashMap<String, u32> = HashMap::new();
-        
-        let scale: f32 = MAX_TERM_WEIGHT as f32 / max_tok_weight;
-        for (key, value) in &query {
-            quant_query.insert(key.clone(), (value * scale).ceil() as u32);
-        }
-        let cursors: Vec<PostingListIterator> = quant_query
-            .iter()
-            .flat_map(|(token, freq)| self.index.get_cursor(token, *freq))
+        check_search_params(alpha, beta)?;
+        let (quantizer, pruning) = query_options(max_weight, rounding, scaling, pruning)?;
+        let query = self.expand_query(query, max_expansions)?;
+        let cursors = cursors_from_weighted_query(&query, &self.index, &quantizer);
+        let hits = self.search_cursors(cursors, pruning.as_ref(), k, alpha, beta);
+        Ok(self.results(hits))
+    }
+
+    /// Searches several queries in parallel, on `threads` threads (all the cores by default),
+    /// without holding the GIL. Returns the results of each query, as `search`.
+    #[pyo3(signature = (queries, k, alpha, beta, threads=None, max_weight=32, rounding="ceil", scaling="per-query", pruning=None, max_expansions=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_batch(
+        &self,
+        py: Python<'_>,
+        queries: Vec<HashMap<String, f32>>,
+        k: usize,
+        alpha: f32,
+        beta: f32,
+        threads: Option<usize>,
+        max_weight: u16,
+        rounding: &str,
+        scaling: &str,
+        pruning: Option<&str>,
+        max_expansions: Option<usize>,
+    ) -> PyResult<Vec<(Vec<String>, Vec<f32>)>> {
+        check_search_params(alpha, beta)?;
+        let (quantizer, pruning) = query_options(max_weight, rounding, scaling, pruning)?;
+        let queries = queries
+            .into_iter()
+            .map(|query| self.expand_query(query, max_expansions))
+            .collect::<PyResult<Vec<_>>>()?;
+        let hits = self.search_parallel(py, &queries, &quantizer, pruning.as_ref(), k, alpha, beta, threads)?;
+        Ok(hits.into_iter().map(|hits| self.results(hits)).collect())
+    }
+
+    /// Like `search_batch`, for the rows of a sparse matrix of query weights whose columns are the
+    /// terms of `vocab`: a SciPy sparse matrix, or a `(data, indices, indptr)` tuple of CSR arrays.
+    #[pyo3(signature = (queries, vocab, k, alpha, beta, threads=None, max_weight=32, rounding="ceil", scaling="per-query", pruning=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_sparse(
+        &self,
+        py: Python<'_>,
+        queries: &Bound<'_, PyAny>,
+        vocab: Vec<String>,
+        k: usize,
+        alpha: f32,
+        beta: f32,
+        threads: Option<usize>,
+        max_weight: u16,
+        rounding: &str,
+        scaling: &str,
+        pruning: Option<&str>,
+    ) -> PyResult<Vec<(Vec<String>, Vec<f32>)>> {
+        check_search_params(alpha, beta)?;
+        let (quantizer, pruning) = query_options(max_weight, rounding, scaling, pruning)?;
+        let matrix = CsrMatrix::extract(queries, vocab.len())?;
+        let queries: Vec<HashMap<String, f32>> = (0..matrix.num_rows())
+            .map(|row| {
+                matrix
+                    .row(row)
+                    .into_iter()
+                    .map(|(column, weight)| (vocab[column as usize].clone(), weight))
+                    .collect()
+            })
             .collect();
+        let hits = self.search_parallel(py, &queries, &quantizer, pruning.as_ref(), k, alpha, beta, threads)?;
+        Ok(hits.into_iter().map(|hits| self.results(hits)).collect())
+    }
+}
+
+/// Parses the query quantization and pruning options.
+fn query_options(
+    max_weight: u16,
+    rounding: &str,
+    scaling: &str,
+    pruning: Option<&str>,
+) -> PyResult<(QueryQuantizer, Option<QueryPruning>)> {
+    if max_weight == 0 {
+        return Err(PyValueError::new_err("max_weight must be positive"));
+    }
+    let rounding: Rounding = rounding
+        .parse()
+        .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    let scaling: Scaling = scaling
+        .parse()
+        .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    let pruning: Option<QueryPruning> = pruning
+        .map(str::parse)
+        .transpose()
+        .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    Ok((QueryQuantizer::new(max_weight, rounding, scaling), pruning))
+}
+
+impl Searcher {
+    /// Expands the patterns of `query` if `max_expansions` is set.
+    fn expand_query(
+        &self,
+        query: HashMap<String, f32>,
+        max_expansions: Option<usize>,
+    ) -> PyResult<HashMap<String, f32>> {
+        match max_expansions {
+            Some(max_expansions) => TermExpansion::new(max_expansions)
+                .expand_query(&query, &self.index)
+                .map_err(|e| PyValueError::new_err(format!("{:#}", e))),
+            None => Ok(query),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn search_parallel(
+        &self,
+        py: Python<'_>,
+        queries: &[HashMap<String, f32>],
+        quantizer: &QueryQuantizer,
+        pruning: Option<&QueryPruning>,
+        k: usize,
+        alpha: f32,
+        beta: f32,
+        threads: Option<usize>,
+    ) -> PyResult<Vec<Hits>> {
+        let pool = threads.map(thread_pool).transpose()?;
+        Ok(py.allow_threads(|| {
+            // Quantized together, so that a global scaling is shared by the queries.
+            let queries = quantizer.quantize_all(queries);
+            let search = || {
+                queries
+                    .par_iter()
+                    .map(|query| {
+                        let cursors = cursors_from_quantized_query(query, &self.index);
+                        self.search_cursors(cursors, pruning, k, alpha, beta)
+                    })
+                    .collect()
+            };
+            match pool {
+                Some(pool) => pool.install(search),
+                None => search(),
+            }
+        }))
+    }
+
+    fn search_cursors(
+        &self,
+        mut cursors: Vec<PostingListIterator>,
+        pruning: Option<&QueryPruning>,
+        k: usize,
+        alpha: f32,
+        beta: f32,
+    ) -> Hits {
+        if let Some(pruning) = pruning {
+            pruning.prune(&mut cursors, self.index.num_documents());
+        }
+        if needs_wide_scores(&cursors) {
+            self.search_scores::<u32>(cursors, k, alpha, beta)
+        } else {
+            self.search_scores::<u16>(cursors, k, alpha, beta)
+        }
+    }
+
+    fn search_scores<S: Score>(
+        &self,
+        cursors: Vec<PostingListIterator>,
+        k: usize,
+        alpha: f32,
+        beta: f32,
+    ) -> Hits {
         let wrapped_cursors = vec![cursors; 1];
-        let mut results = b_search_verbose(wrapped_cursors, &self.bfwd, k, alpha, beta, false);
+        let mut results =
+            b_search_verbose::<S>(wrapped_cursors, &self.bfwd, k, alpha, beta, false);
+        results[0]
+            .to_sorted_vec()
+            .iter()
+            .map(|r| (r.doc_id.0, r.score.to_f32()))
+            .collect()
+    }
+
+    /// Doc IDs and scores of `hits`.
+    fn results(&self, hits: Hits) -> (Vec<String>, Vec<f32>) {
         let doc_lexicon = self.index.documents();
-        let mut docnos: Vec<String> = Vec::new();
-        let mut scores: Vec<f32> = Vec::new();
-        for r in results[0].to_sorted_vec().iter() {
-            docnos.push(doc_lexicon[r.doc_id.0 as usize].clone());
-            scores.push(r.score.into());
-        }
-        Ok((docnos, scores))
+        hits.into_iter()
+            .map(|(doc_id, score)| (doc_lexicon.get(doc_id).unwrap_or_default(), score))
+            .unzip()
     }
 }
 
 #[pyfunction]
+#[pyo3(signature = (index, queries, k, alpha, beta, pruning=None, quiet=false))]
+#[allow(clippy::too_many_arguments)]
 fn search(
     index: PathBuf,
     queries: PathBuf,
     k: usize,
     alpha: f32,
     beta: f32,
+    pruning: Option<&str>,
+    quiet: bool,
 ) -> PyResult<String> {
-    eprintln!("Loading the index");
-    let (index, bfwd) = bmp::index::from_file(index).expect("Index cannot be loaded.");
+    check_search_params(alpha, beta)?;
+    let pruning: Option<QueryPruning> = pruning
+        .map(str::parse)
+        .transpose()
+        .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+
+    log::info!("Loading the index");
+    let (index, bfwd) = BmpIndex::open(index).map_err(bmp_error)?.into_parts();
 
     // 2. Load the queries
-    eprintln!("Loading the queries");
-    let (q_ids, cursors) = cursors_from_queries(queries, &index);
+    log::info!("Loading the queries");
+    let (q_ids, mut cursors) = cursors_from_queries(queries, &index).map_err(bmp_error)?;
+    if let Some(pruning) = pruning {
+        for query in cursors.iter_mut() {
+            pruning.prune(query, index.num_documents());
+        }
+    }
 
-    eprintln!("Performing query processing");
-    let results = b_search_verbose(cursors, &bfwd, k, alpha, beta, true);
+    log::info!("Performing query processing");
+    let run = if cursors.iter().any(|query| needs_wide_scores(query)) {
+        let results = b_search_verbose::<u32>(cursors, &bfwd, k, alpha, beta, !quiet);
+        to_trec(&q_ids, results, index.documents())
+    } else {
+        let results = b_search_verbose::<u16>(cursors, &bfwd, k, alpha, beta, !quiet);
+        to_trec(&q_ids, results, index.documents())
+    };
 
-    eprintln!("Exporting TREC run");
+    log::info!("Exporting TREC run");
     // 4. Log results into TREC format
-    Ok(to_trec(&q_ids, results, index.documents()))
+    Ok(run)
 }
 
 #[pyclass]
 struct InvertedIndexer {
     path: PathBuf,
     bsize: usize,
-    compress_range: bool,
-    inv_builder: bmp::index::inverted_index::IndexBuilder,
-    fwd_builder: bmp::index::forward_index::ForwardIndexBuilder,
+    writer: IndexWriter,
 }
 
 #[pymethods]
 impl InvertedIndexer {
 
     #[new]
-    fn py_new(path: PathBuf, bsize: usize, compress_range: bool) -> PyResult<Self> {
+    #[pyo3(signature = (path, bsize, compress_range, min_impact=None, posting_list_fraction=None, terms_per_document=None, quiet=false))]
+    fn py_new(
+        path: PathBuf,
+        bsize: usize,
+        compress_range: bool,
+        min_impact: Option<u32>,
+        posting_list_fraction: Option<f32>,
+        terms_per_document: Option<usize>,
+        quiet: bool,
+    ) -> PyResult<Self> {
+        check_bsize(bsize)?;
+        let writer = index_writer(bsize, compress_range, min_impact, posting_list_fraction, terms_per_document, quiet)?;
         Ok(InvertedIndexer {
-            path: path,
-            bsize: bsize,
-            compress_range: compress_range,
-            inv_builder: bmp::index::inverted_index::IndexBuilder::new(0, bsize),
-            fwd_builder: bmp::index::forward_index::ForwardIndexBuilder::new(0),
+            path,
+            bsize,
+            writer,
         })
     }
 
+    /// Adds a document, whose postings are given by `add_term`, with optional `metadata` (a dict
+    /// serializable to JSON). The forward index is built from the posting lists: `vector` is
+    /// deprecated, and ignored with a `DeprecationWarning`.
+    #[pyo3(signature = (doc_id, vector=None, metadata=None))]
     fn add_document(
         &mut self,
+        py: Python<'_>,
         doc_id: String,
-        vector: Vec<(u32, u32)>,
+        vector: Option<&Bound<'_, PyAny>>,
+        metadata: Option<&Bound<'_, PyDict>>,
     ) -> PyResult<()> {
-      self.inv_builder.insert_document(&doc_id);
-      self.fwd_builder.insert_document(vector);
-      Ok(())
+      if vector.is_some() {
+          PyErr::warn_bound(
+              py,
+              &py.get_type_bound::<PyDeprecationWarning>(),
+              "the vector of InvertedIndexer.add_document is ignored, documents get the postings given by add_term",
+              1,
+          )?;
+      }
+      let metadata = metadata.map(to_json).transpose()?;
+      let doc_idx = self.writer.add_document_name(&doc_id);
+      set_metadata(&mut self.writer, doc_idx, metadata.as_deref())
     }
 
     fn add_term(
@@ -129,23 +612,14 @@ impl InvertedIndexer {
         term: String,
         postings: Vec<(u32, u32)>,
     ) -> PyResult<()> {
-      self.inv_builder.insert_term(&term, postings);
+      self.writer.add_posting_list(&term, postings).map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
       Ok(())
     }
 
     fn finish(
         &mut self,
     ) -> PyResult<()> {
-        let builder = std::mem::replace(&mut self.inv_builder, bmp::index::inverted_index::IndexBuilder::new(0, 0));
-        let inverted_index = builder.build(self.compress_range);
-        let forward_index = self.fwd_builder.build();
-        let b_forward_index = bmp::index::forward_index::fwd2bfwd(&forward_index, self.bsize);
-        let file = std::fs::File::create(self.path.clone()).expect("Failed to create file");
-        let writer = std::io::BufWriter::new(file);
-        // Serialize the index directly into a file using bincode
-        bincode::serialize_into(writer, &(&inverted_index, &b_forward_index))
-            .expect("Failed to serialize");
-        Ok(())
+        finish_writer(&mut self.writer, self.bsize, &self.path)
     }
 }
 
@@ -153,61 +627,287 @@ impl InvertedIndexer {
 struct Indexer {
     path: PathBuf,
     bsize: usize,
-    compress_range: bool,
-    inv_builder: bmp::index::inverted_index::IndexBuilder,
-    fwd_builder: bmp::index::forward_index::ForwardIndexBuilder,
-    term_map: HashMap<String, u32>,
+    writer: IndexWriter,
 }
 
 #[pymethods]
 impl Indexer {
 
     #[new]
-    fn py_new(path: PathBuf, bsize: usize, compress_range: bool) -> PyResult<Self> {
+    #[pyo3(signature = (path, bsize, compress_range, min_impact=None, posting_list_fraction=None, terms_per_document=None, quiet=false))]
+    fn py_new(
+        path: PathBuf,
+        bsize: usize,
+        compress_range: bool,
+        min_impact: Option<u32>,
+        posting_list_fraction: Option<f32>,
+        terms_per_document: Option<usize>,
+        quiet: bool,
+    ) -> PyResult<Self> {
+        check_bsize(bsize)?;
+        let writer = index_writer(bsize, compress_range, min_impact, posting_list_fraction, terms_per_document, quiet)?;
         Ok(Indexer {
-            path: path,
-            bsize: bsize,
-            compress_range: compress_range,
-            inv_builder: bmp::index::inverted_index::IndexBuilder::new(0, bsize),
-            fwd_builder: bmp::index::forward_index::ForwardIndexBuilder::new(0),
-            term_map: HashMap::new(),
+            path,
+            bsize,
+            writer,
         })
     }
 
+    /// Adds a document with the impacts of its terms, and optional `metadata` (a dict
+    /// serializable to JSON) returned with the search results.
+    #[pyo3(signature = (doc_id, vector, metadata=None))]
     fn add_document(
         &mut self,
         doc_id: String,
         vector: HashMap<String, u32>,
+        metadata: Option<&Bound<'_, PyDict>>,
     ) -> PyResult<()> {
-        let doc_idx = self.inv_builder.insert_document(&doc_id);
-        let mut int_vector: Vec<(u32, u32)> = Vec::new();
-        for (term, weight) in &vector {
-            if !self.term_map.contains_key(term) {
-                self.term_map.insert(term.clone(), self.term_map.len() as u32);
-                self.inv_builder.insert_term(term, Vec::new());
-            }
-            let term_idx = self.term_map[term];
-            self.inv_builder.push_posting(term_idx, doc_idx, *weight);
-            int_vector.push((term_idx, *weight))
+        // Checked before adding the document, which is then added in full.
+        let metadata = metadata.map(to_json).transpose()?;
+        let int_vector: Vec<(u32, u32)> = vector
+            .iter()
+            .map(|(term, &weight)| (self.writer.term_id(term), weight))
+            .collect();
+        let doc_idx = self.writer.add_document(&doc_id, &int_vector).map_err(bmp_error)?;
+        set_metadata(&mut self.writer, doc_idx, metadata.as_deref())
+    }
+
+    /// Adds the documents `doc_ids`, whose float weights are the rows of a sparse matrix whose
+    /// columns are the terms of `vocab`: a SciPy sparse matrix, or a `(data, indices, indptr)`
+    /// tuple of CSR arrays. Weights are multiplied by `scale` and truncated into impacts of at
+    /// most 255; those truncated to zero are dropped. `metadata` optionally gives the metadata of
+    /// each document, a dict or `None`.
+    #[pyo3(signature = (doc_ids, documents, vocab, scale=100.0, metadata=None))]
+    fn add_sparse(
+        &mut self,
+        py: Python<'_>,
+        doc_ids: Vec<String>,
+        documents: &Bound<'_, PyAny>,
+        vocab: Vec<String>,
+        scale: f32,
+        metadata: Option<Vec<Option<Bound<'_, PyDict>>>>,
+    ) -> PyResult<()> {
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(PyValueError::new_err("scale must be positive"));
+        }
+        let matrix = CsrMatrix::extract(documents, vocab.len())?;
+        if matrix.num_rows() != doc_ids.len() {
+            return Err(PyValueError::new_err(format!(
+                "{} doc IDs for {} documents",
+                doc_ids.len(),
+                matrix.num_rows()
+            )));
         }
-        self.fwd_builder.insert_document(int_vector);
-        Ok(())
+        let metadata = metadata
+            .map(|metadata| {
+                if metadata.len() != doc_ids.len() {
+                    return Err(PyValueError::new_err(format!(
+                        "{} metadata for {} documents",
+                        metadata.len(),
+                        doc_ids.len()
+                    )));
+                }
+                metadata.iter().map(|metadata| metadata.as_ref().map(to_json).transpose()).collect()
+            })
+            .transpose()?
+            .unwrap_or_else(|| vec![None; doc_ids.len()]);
+        py.allow_threads(|| {
+            // Term ids of the columns, looked up when first used.
+            let mut columns: Vec<Option<u32>> = vec![None; vocab.len()];
+            for (row, doc_id) in doc_ids.iter().enumerate() {
+                let mut int_vector: Vec<(u32, u32)> = Vec::new();
+                for (column, weight) in matrix.row(row) {
+                    let impact = quantize_impact(weight, scale);
+                    if impact == 0 {
+                        continue;
+                    }
+                    let term_idx = *columns[column as usize]
+                        .get_or_insert_with(|| self.writer.term_id(&vocab[column as usize]));
+                    int_vector.push((term_idx, impact));
+                }
+                let doc_idx = self.writer.add_document(doc_id, &int_vector)?;
+                if let Some(metadata) = &metadata[row] {
+                    self.writer.set_metadata(doc_idx, metadata)?;
+                }
+            }
+            Ok(())
+        })
+        .map_err(bmp_error)
     }
 
     fn finish(
         &mut self,
     ) -> PyResult<()> {
-        let builder = std::mem::replace(&mut self.inv_builder, bmp::index::inverted_index::IndexBuilder::new(0, 0));
-        let inverted_index = builder.build(self.compress_range);
-        let forward_index = self.fwd_builder.build();
-        let b_forward_index = bmp::index::forward_index::fwd2bfwd(&forward_index, self.bsize);
-        let file = std::fs::File::create(self.path.clone()).expect("Failed to create file");
-        let writer = std::io::BufWriter::new(file);
-        // Serialize the index directly into a file using bincode
-        bincode::serialize_into(writer, &(&inverted_index, &b_forward_index))
-            .expect("Failed to serialize");
-        Ok(())
+        finish_writer(&mut self.writer, self.bsize, &self.path)
+    }
+}
+
+/// Serializes the metadata of a document to JSON with the `json` module, and checks that it can be
+/// stored, so that documents are not added without their metadata.
+fn to_json(dict: &Bound<'_, PyDict>) -> PyResult<String> {
+    let dumps = dict.py().import_bound("json")?.getattr("dumps")?;
+    let json: String = dumps.call1((dict,))?.extract()?;
+    DocumentMetadata::check(&json).map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
+    Ok(json)
+}
+
+/// Sets the metadata of document `doc_idx`, if any.
+fn set_metadata(writer: &mut IndexWriter, doc_idx: u32, metadata: Option<&str>) -> PyResult<()> {
+    if let Some(metadata) = metadata {
+        writer
+            .set_metadata(doc_idx, metadata)
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
+    }
+    Ok(())
+}
+
+/// Writer with the indexing options of `Indexer` and `InvertedIndexer`.
+fn index_writer(
+    bsize: usize,
+    compress_range: bool,
+    min_impact: Option<u32>,
+    posting_list_fraction: Option<f32>,
+    terms_per_document: Option<usize>,
+    quiet: bool,
+) -> PyResult<IndexWriter> {
+    let mut writer = IndexWriter::new(bsize);
+    writer
+        .compress_range(compress_range)
+        .static_pruning(static_pruning(min_impact, posting_list_fraction, terms_per_document)?)
+        .quiet(quiet);
+    Ok(writer)
+}
+
+/// Builds the index of `writer`, which is replaced by an empty one, and writes it to `path`. The
+/// writer is kept if the index cannot be built, e.g. to add the missing documents.
+fn finish_writer(writer: &mut IndexWriter, bsize: usize, path: &PathBuf) -> PyResult<()> {
+    writer
+        .check()
+        .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
+    let writer = std::mem::replace(writer, IndexWriter::new(bsize));
+    let index = writer.finish().map_err(bmp_error)?;
+    index.write_to(path).map_err(bmp_error)
+}
+
+/// Sparse matrix in the compressed sparse row format: the columns and values of row `i` are at
+/// positions `indptr[i]..indptr[i + 1]` of `indices` and `data`.
+struct CsrMatrix {
+    indptr: Vec<usize>,
+    indices: Vec<u32>,
+    data: Vec<f32>,
+}
+
+impl CsrMatrix {
+    /// Reads a SciPy sparse matrix or array, converted to CSR, or a `(data, indices, indptr)`
+    /// tuple of arrays, as taken by `scipy.sparse.csr_matrix`, with `num_columns` columns.
+    fn extract(matrix: &Bound<'_, PyAny>, num_columns: usize) -> PyResult<Self> {
+        let (data, indices, indptr) = if let Ok(tuple) = matrix.downcast::<PyTuple>() {
+            tuple.extract::<(Bound<PyAny>, Bound<PyAny>, Bound<PyAny>)>()?
+        } else {
+            let matrix = if matrix.hasattr("tocsr")? {
+                matrix.call_method0("tocsr")?
+            } else {
+                matrix.clone()
+            };
+            (
+                matrix.getattr("data")?,
+                matrix.getattr("indices")?,
+                matrix.getattr("indptr")?,
+            )
+        };
+        let matrix = CsrMatrix {
+            indptr: index_array(&indptr, "indptr")?,
+            indices: index_array(&indices, "indices")?,
+            data: float_array(&data)?,
+        };
+        if matrix.indptr.is_empty()
+            || matrix.indptr.windows(2).any(|w| w[0] > w[1])
+            || matrix.indptr[matrix.indptr.len() - 1] > matrix.indices.len()
+        {
+            return Err(PyValueError::new_err("indptr must be non-decreasing offsets into indices"));
+        }
+        if matrix.indices.len() != matrix.data.len() {
+            return Err(PyValueError::new_err("indices and data must have the same length"));
+        }
+        if matrix.indices.iter().any(|&column| column as usize >= num_columns) {
+            return Err(PyValueError::new_err(format!(
+                "column index out of range for a vocabulary of {} terms",
+                num_columns
+            )));
+        }
+        Ok(matrix)
+    }
+
+    fn num_rows(&self) -> usize {
+        self.indptr.len() - 1
+    }
+
+    /// Columns and values of a row, by column, with the values of duplicate columns summed.
+    fn row(&self, row: usize) -> Vec<(u32, f32)> {
+        let range = self.indptr[row]..self.indptr[row + 1];
+        let mut entries: Vec<(u32, f32)> = self.indices[range.clone()]
+            .iter()
+            .copied()
+            .zip(self.data[range].iter().copied())
+            .collect();
+        entries.sort_unstable_by_key(|&(column, _)| column);
+        entries.dedup_by(|next, first| {
+            if next.0 == first.0 {
+                first.1 += next.1;
+            }
+            next.0 == first.0
+        });
+        entries
+    }
+}
+
+/// Reads `obj` through the buffer protocol if its items are of type `T`.
+fn buffer_to_vec<T: Element>(obj: &Bound<'_, PyAny>) -> Option<PyResult<Vec<T>>> {
+    let buffer = PyBuffer::<T>::get_bound(obj).ok()?;
+    if buffer.dimensions() != 1 {
+        return Some(Err(PyValueError::new_err("expected a one-dimensional array")));
     }
+    Some(buffer.to_vec(obj.py()))
+}
+
+/// Reads a one-dimensional array of integers of type `T` into a vector of `U`.
+fn convert_ints<T: Element, U: TryFrom<T>>(
+    obj: &Bound<'_, PyAny>,
+    name: &str,
+) -> Option<PyResult<Vec<U>>> {
+    let values = buffer_to_vec::<T>(obj)?;
+    Some(values.and_then(|values| {
+        values
+            .into_iter()
+            .map(U::try_from)
+            .collect::<Result<_, _>>()
+            .map_err(|_| PyValueError::new_err(format!("{} contains invalid indices", name)))
+    }))
+}
+
+/// Reads a one-dimensional array of non-negative integers, such as a NumPy array of any integer
+/// type or a list.
+fn index_array<U>(obj: &Bound<'_, PyAny>, name: &str) -> PyResult<Vec<U>>
+where
+    U: TryFrom<i32> + TryFrom<i64> + TryFrom<u32> + TryFrom<u64> + for<'py> FromPyObject<'py>,
+{
+    convert_ints::<i32, U>(obj, name)
+        .or_else(|| convert_ints::<i64, U>(obj, name))
+        .or_else(|| convert_ints::<u32, U>(obj, name))
+        .or_else(|| convert_ints::<u64, U>(obj, name))
+        .unwrap_or_else(|| obj.extract())
+}
+
+/// Reads a one-dimensional array of floats, such as a NumPy array of `float32` or `float64`, or a
+/// list.
+fn float_array(obj: &Bound<'_, PyAny>) -> PyResult<Vec<f32>> {
+    if let Some(values) = buffer_to_vec::<f32>(obj) {
+        return values;
+    }
+    if let Some(values) = buffer_to_vec::<f64>(obj) {
+        return Ok(values?.into_iter().map(|value| value as f32).collect());
+    }
+    obj.extract()
 }
 
 /// A Python module implemented in Rust. The name of this function must match
@@ -215,10 +915,16 @@ impl Indexer {
 /// import the module.
 #[pymodule]
 fn _bmp(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    // Fails if the module is initialized again, with the logger already installed.
+    if log::set_boxed_logger(Box::new(PythonLogger)).is_ok() {
+        log::set_max_level(log::LevelFilter::Info);
+    }
     m.add_function(wrap_pyfunction!(ciff2bmp, m)?)?;
+    m.add_function(wrap_pyfunction!(jsonl2bmp, m)?)?;
     m.add_function(wrap_pyfunction!(search, m)?)?;
     m.add_class::<Searcher>()?;
     m.add_class::<InvertedIndexer>()?;
     m.add_class::<Indexer>()?;
+    m.add("BmpError", m.py().get_type_bound::<BmpError>())?;
     Ok(())
 }
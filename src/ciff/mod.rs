@@ -1,15 +1,13 @@
 use anyhow::{anyhow, Context};
-use indicatif::{ProgressBar, ProgressStyle};
 use num_traits::ToPrimitive;
 use protobuf::CodedInputStream;
 use std::fmt;
 use std::fs::File;
-use std::io::BufWriter;
-use std::io::{Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 
-use crate::index::forward_index::ForwardIndexBuilder;
-use crate::index::inverted_index::IndexBuilder;
+use crate::index::pruning::StaticPruning;
+use crate::index::writer::IndexWriter;
+use crate::util::quiet_progress_bar;
 
 pub use crate::proto::{DocRecord, Posting, PostingsList};
 
@@ -52,16 +50,6 @@ impl fmt::Display for Header {
     }
 }
 
-const DEFAULT_PROGRESS_TEMPLATE: &str =
-    "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {count}/{total} ({eta})";
-
-/// Returns default progress style.
-fn pb_style() -> ProgressStyle {
-    ProgressStyle::default_bar()
-        .template(DEFAULT_PROGRESS_TEMPLATE)
-        .progress_chars("=> ")
-}
-
 /// CIFF to BMP converter.
 #[derive(Debug, Default, Clone)]
 pub struct CiffToBmp {
@@ -69,6 +57,8 @@ pub struct CiffToBmp {
     output: Option<PathBuf>,
     bsize: Option<usize>,
     compress_range: bool,
+    pruning: StaticPruning,
+    quiet: bool,
 }
 
 impl CiffToBmp {
@@ -92,6 +82,16 @@ impl CiffToBmp {
         self.compress_range = compress_range;
         self
     }
+    /// Sets the static pruning applied to the postings. Defaults to keeping every posting.
+    pub fn static_pruning(&mut self, pruning: StaticPruning) -> &mut Self {
+        self.pruning = pruning;
+        self
+    }
+    /// Hides the progress bars. Messages are logged with the `log` crate either way.
+    pub fn quiet(&mut self, quiet: bool) -> &mut Self {
+        self.quiet = quiet;
+        self
+    }
     /// Builds a BMP index using the previously defined parameters.
     ///
     /// # Errors
@@ -108,134 +108,100 @@ impl CiffToBmp {
         let output = self
             .output
             .as_ref()
-            .ok_or_else(|| anyhow!("input path undefined"))?;
+            .ok_or_else(|| anyhow!("output path undefined"))?;
         let bsize = self.bsize.ok_or_else(|| anyhow!("bsize undefined"))?;
-        convert_to_bmp(input, output, bsize, self.compress_range)
+        convert_to_bmp(
+            input,
+            output,
+            bsize,
+            self.compress_range,
+            &self.pruning,
+            self.quiet,
+        )
     }
 }
 
-fn convert_to_bmp(input: &Path, output: &Path, bsize: usize, compress_range: bool) -> Result<()> {
-    println!("{:?}", output);
+fn convert_to_bmp(
+    input: &Path,
+    output: &Path,
+    bsize: usize,
+    compress_range: bool,
+    pruning: &StaticPruning,
+    quiet: bool,
+) -> Result<()> {
+    log::info!("Converting {} to {}", input.display(), output.display());
     let mut ciff_reader =
         File::open(input).with_context(|| format!("Unable to open {}", input.display()))?;
+    let mut input = CodedInputStream::new(&mut ciff_reader);
 
-    let mut builder: IndexBuilder;
-    {
-        let mut input = CodedInputStream::new(&mut ciff_reader);
-
-        let header: Header = Header::from_stream(&mut input)?;
-        println!("{}", header);
-
-        builder = IndexBuilder::new(header.num_documents as usize, bsize);
-
-        eprintln!("Processing postings");
-        let progress = ProgressBar::new(u64::try_from(header.num_postings_lists)?);
-        progress.set_style(pb_style());
-        progress.set_draw_delta(10);
-
-        for _ in 0..header.num_postings_lists {
-            let list = input.read_message::<PostingsList>()?;
-            let mut docid = 0;
-            let postings: Vec<(u32, u32)> = list
-                .get_postings()
-                .iter()
-                .map(|p| {
-                    docid += u32::try_from(p.get_docid()).expect("Negative docID");
-                    (
-                        docid,
-                        u32::try_from(p.get_tf()).expect("Negative frequency"),
-                    )
-                })
-                .collect();
-            builder.insert_term(list.term.as_str(), postings);
-            progress.inc(1);
-        }
-        progress.finish();
-
-        eprintln!("Processing document names");
-
-        let progress = ProgressBar::new(u64::from(header.num_documents));
-        progress.set_style(pb_style());
-        progress.set_draw_delta(u64::from(header.num_documents) / 100);
+    let header: Header = Header::from_stream(&mut input)?;
+    log::info!("{}", header);
 
-        for docs_seen in 0..header.num_documents {
-            let doc_record = input.read_message::<DocRecord>()?;
+    let mut writer = IndexWriter::new(bsize);
+    writer
+        .compress_range(compress_range)
+        .static_pruning(*pruning)
+        .quiet(quiet);
 
-            let docid: u32 = doc_record
-                .get_docid()
-                .to_u32()
-                .ok_or_else(|| anyhow!("Cannot cast docid to u32: {}", doc_record.get_docid()))?;
+    log::info!("Processing postings");
+    let progress = quiet_progress_bar(
+        "Building forward index",
+        header.num_postings_lists as usize,
+        quiet,
+    );
+    progress.set_draw_delta(10);
 
-            let trecid = doc_record.get_collection_docid();
-            if docid != docs_seen {
-                anyhow::bail!("Document sizes must come in order");
-            }
-            builder.insert_document(trecid);
-            progress.inc(1);
-        }
-        progress.finish();
+    for _ in 0..header.num_postings_lists {
+        let list = input.read_message::<PostingsList>()?;
+        let postings = decode_postings(&list)?;
+        writer.add_posting_list(list.term.as_str(), postings)?;
+        progress.inc(1);
     }
-    let inverted_index = builder.build(compress_range);
+    progress.finish();
 
-    // Seek to the beginning of the file
-    ciff_reader.seek(SeekFrom::Start(0))?;
+    log::info!("Processing document names");
 
-    // Recreate the CodedInputStream with the file reader reset to the beginning
-    let mut input = CodedInputStream::new(&mut ciff_reader);
-    let header = Header::from_stream(&mut input)?;
+    let progress = quiet_progress_bar(
+        "Processing document names",
+        header.num_documents as usize,
+        quiet,
+    );
+    progress.set_draw_delta(u64::from(header.num_documents) / 100);
 
-    eprintln!("Building forward index");
-    let progress = ProgressBar::new(header.num_postings_lists as u64);
-    progress.set_style(pb_style());
-    progress.set_draw_delta((header.num_postings_lists / 100) as u64);
+    for docs_seen in 0..header.num_documents {
+        let doc_record = input.read_message::<DocRecord>()?;
 
-    let mut fwd_builder = ForwardIndexBuilder::new(header.num_documents as usize);
+        let docid: u32 = doc_record
+            .get_docid()
+            .to_u32()
+            .ok_or_else(|| anyhow!("Cannot cast docid to u32: {}", doc_record.get_docid()))?;
 
-    for term_id in 0..header.num_postings_lists {
-        let list = input.read_message::<PostingsList>()?;
-        let mut docid = 0;
-        let posting_list: Vec<(u32, u32)> = list
-            .get_postings()
-            .iter()
-            .map(|p| {
-                docid += u32::try_from(p.get_docid()).expect("Negative docID");
-                (
-                    docid,
-                    u32::try_from(p.get_tf()).expect("Negative frequency"),
-                )
-            })
-            .collect();
-
-        fwd_builder.insert_posting_list(term_id as u32, &posting_list);
+        let trecid = doc_record.get_collection_docid();
+        if docid != docs_seen {
+            anyhow::bail!("Document sizes must come in order");
+        }
+        writer.add_document_name(trecid);
         progress.inc(1);
     }
-    // for (term_id, posting_list) in inverted_index.posting_lists().iter().enumerate() {
-    //     fwd_builder.insert_posting_list(term_id as u32, posting_list);
-    //     progress.inc(1);
-    // }
     progress.finish();
-    eprintln!("Converting to blocked forward index");
-
-    let forward_index = fwd_builder.build();
-    let b_forward_index = crate::index::forward_index::fwd2bfwd(&forward_index, bsize);
-    eprintln!("block numbers: {}", b_forward_index.data.len());
-    let mut tot = 0;
-    let mut tot_avg_docs = 0.0;
-    for (_, block) in b_forward_index.data.iter().enumerate() {
-        tot += block.len();
-        tot_avg_docs +=
-            block.iter().map(|(_, v)| v.len()).sum::<usize>() as f32 / block.len() as f32;
-    }
-    eprintln!("avg terms per block: {}", tot / b_forward_index.data.len());
-    eprintln!(
-        "avg docs per term: {}",
-        tot_avg_docs / b_forward_index.data.len() as f32
-    );
-    let file = File::create(output).expect("Failed to create file");
-    let writer = BufWriter::new(file);
-    // Serialize the index directly into a file using bincode
-    bincode::serialize_into(writer, &(&inverted_index, &b_forward_index))
-        .expect("Failed to serialize");
 
-    Ok(())
+    writer.finish()?.write_to(output)
+}
+
+/// Document ids, from their gaps, and frequencies of the postings of a CIFF posting list.
+fn decode_postings(list: &PostingsList) -> Result<Vec<(u32, u32)>> {
+    let mut docid: u32 = 0;
+    list.get_postings()
+        .iter()
+        .map(|p| {
+            let gap = u32::try_from(p.get_docid())
+                .with_context(|| format!("Negative docID gap in the list of {}", list.term))?;
+            docid = docid
+                .checked_add(gap)
+                .ok_or_else(|| anyhow!("DocID overflow in the list of {}", list.term))?;
+            let tf = u32::try_from(p.get_tf())
+                .with_context(|| format!("Negative frequency in the list of {}", list.term))?;
+            Ok((docid, tf))
+        })
+        .collect()
 }
@@ -0,0 +1,163 @@
+use super::forward_index::{read_varint, write_varint};
+use super::inverted_index::{deserialize_fst_map, serialize_fst_map};
+use fst::{Map, MapBuilder};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Number of names per front-coded bucket.
+const BUCKET_SIZE: usize = 16;
+
+/// Names of the documents, by document id, with the reverse lookup of ids by name.
+///
+/// Names are front-coded in id order: each bucket of [`BUCKET_SIZE`] names starts with a full
+/// name, and the next ones only store their suffix after the prefix shared with the previous one,
+/// which is most of a name for sequential ids such as MS MARCO's. Ids are looked up in an FST
+/// mapping each name to the first document with that name.
+#[derive(Default, Serialize, Deserialize)]
+pub struct DocumentLexicon {
+    len: usize,
+    data: Vec<u8>,
+    bucket_offsets: Vec<usize>,
+    #[serde(
+        serialize_with = "serialize_fst_map",
+        deserialize_with = "deserialize_fst_map"
+    )]
+    ids: Map<Vec<u8>>,
+}
+
+impl DocumentLexicon {
+    /// Builds the lexicon of `names`, given by document id.
+    pub fn new<S: AsRef<str>>(names: &[S]) -> Self {
+        let mut data = Vec::new();
+        let mut bucket_offsets = Vec::with_capacity(names.len().div_ceil(BUCKET_SIZE));
+        for bucket in names.chunks(BUCKET_SIZE) {
+            bucket_offsets.push(data.len());
+            let mut previous: &[u8] = &[];
+            for name in bucket {
+                let name = name.as_ref().as_bytes();
+                let shared = previous
+                    .iter()
+                    .zip(name)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                write_varint(&mut data, shared as u32);
+                write_varint(&mut data, (name.len() - shared) as u32);
+                data.extend_from_slice(&name[shared..]);
+                previous = name;
+            }
+        }
+
+        let mut sorted: Vec<(&str, u64)> = names
+            .iter()
+            .enumerate()
+            .map(|(doc_id, name)| (name.as_ref(), doc_id as u64))
+            .collect();
+        sorted.par_sort_unstable();
+        // Duplicate names are mapped to their first document.
+        sorted.dedup_by_key(|&mut (name, _)| name);
+        let mut builder = MapBuilder::memory();
+        for (name, doc_id) in sorted {
+            builder
+                .insert(name, doc_id)
+                .expect("names are inserted in order");
+        }
+        let ids = Map::new(builder.into_inner().expect("in-memory FST")).expect("valid FST");
+
+        DocumentLexicon {
+            len: names.len(),
+            data,
+            bucket_offsets,
+            ids,
+        }
+    }
+
+    /// Number of documents.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Name of document `doc_id`, or `None` if there is no such document.
+    pub fn get(&self, doc_id: u32) -> Option<String> {
+        let doc_id = doc_id as usize;
+        if doc_id >= self.len {
+            return None;
+        }
+        let mut pos = self.bucket_offsets[doc_id / BUCKET_SIZE];
+        let mut name = Vec::new();
+        for _ in 0..=doc_id % BUCKET_SIZE {
+            self.decode_next(&mut pos, &mut name);
+        }
+        Some(String::from_utf8_lossy(&name).into_owned())
+    }
+
+    /// Id of the first document named `name`, or `None` if there is none.
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).map(|doc_id| doc_id as u32)
+    }
+
+    /// Names of the documents, by document id.
+    pub fn iter(&self) -> impl Iterator<Item = String> + '_ {
+        let mut pos = 0;
+        let mut name = Vec::new();
+        (0..self.len).map(move |doc_id| {
+            if doc_id % BUCKET_SIZE == 0 {
+                name.clear();
+            }
+            self.decode_next(&mut pos, &mut name);
+            String::from_utf8_lossy(&name).into_owned()
+        })
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        self.data.len()
+            + self.bucket_offsets.len() * std::mem::size_of::<usize>()
+            + self.ids.as_fst().as_bytes().len()
+    }
+
+    /// Replaces `name`, the previous name of the bucket, by the one at `pos`.
+    fn decode_next(&self, pos: &mut usize, name: &mut Vec<u8>) {
+        let shared = read_varint(&self.data, pos) as usize;
+        let suffix_len = read_varint(&self.data, pos) as usize;
+        name.truncate(shared);
+        name.extend_from_slice(&self.data[*pos..*pos + suffix_len]);
+        *pos += suffix_len;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_document_lexicon() {
+        let names: Vec<String> = (0..40)
+            .map(|i| format!("msmarco_passage_{:02}", i))
+            .chain([
+                "b".to_string(),
+                "".to_string(),
+                "é".to_string(),
+                "a".to_string(),
+            ])
+            .chain(["msmarco_passage_07".to_string()])
+            .collect();
+        let lexicon = DocumentLexicon::new(&names);
+        assert_eq!(lexicon.len(), names.len());
+        assert_eq!(lexicon.iter().collect::<Vec<_>>(), names);
+        for (doc_id, name) in names.iter().enumerate() {
+            assert_eq!(lexicon.get(doc_id as u32).as_ref(), Some(name));
+        }
+        assert_eq!(lexicon.get(names.len() as u32), None);
+
+        assert_eq!(lexicon.id("msmarco_passage_39"), Some(39));
+        assert_eq!(lexicon.id("é"), Some(42));
+        assert_eq!(lexicon.id(""), Some(41));
+        // The first of the duplicates.
+        assert_eq!(lexicon.id("msmarco_passage_07"), Some(7));
+        assert_eq!(lexicon.id("msmarco_passage_40"), None);
+        assert!(lexicon.size_in_bytes() < names.iter().map(String::len).sum::<usize>() * 2);
+    }
+}
@@ -1,3 +1,6 @@
+use super::pruning::PostingFilter;
+use super::simd;
+use crate::query::score::Score;
 use indicatif::ProgressStyle;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -18,16 +21,283 @@ pub struct BlockDocument {
     pub docs_impacts: Vec<Vec<(u8, u8)>>,
 }
 
+/// Forward index grouping documents into blocks of `block_size` documents.
+///
+/// All blocks are stored back to back in a single buffer. A block starts with the number of
+/// terms it contains and the byte lengths of the next two sections. Then come, by increasing term
+/// id: the differences with the previous term ids, the postings headers, and the postings. A
+/// header is the number of postings shifted left by one, with the lowest bit flagging the dense
+/// layout. Sparse postings are `(document offset, impact)` byte pairs, while dense postings hold
+/// one impact byte for each of the `block_size` documents, so that terms appearing in most
+/// documents of a block take less space. All but the postings are varint-coded.
+///
+/// Keeping term ids apart from the postings lets [`block_score`] skip runs of terms without
+/// touching their postings.
 #[derive(Default, Serialize, Deserialize, Clone)]
 pub struct BlockForwardIndex {
-    pub data: Vec<Vec<(u16, Vec<(u8, u8)>)>>,
+    data: Vec<u8>,
+    /// Start of each block in `data`, followed by the end of the last block.
+    offsets: Vec<u64>,
     pub block_size: usize,
 }
 
+impl BlockForwardIndex {
+    pub fn num_blocks(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    /// Encoded data of the block.
+    #[inline]
+    pub fn block(&self, block_id: usize) -> &[u8] {
+        &self.data[self.offsets[block_id] as usize..self.offsets[block_id + 1] as usize]
+    }
+
+    /// Iterates over the terms of the block, with their postings.
+    pub fn block_terms(&self, block_id: usize) -> BlockTerms<'_> {
+        BlockTerms::new(self.block(block_id), self.block_size)
+    }
+
+    pub fn num_postings(&self) -> usize {
+        (0..self.num_blocks())
+            .map(|block_id| {
+                self.block_terms(block_id)
+                    .map(|(_, postings)| postings.len())
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Memory used by the blocks and their offsets.
+    pub fn size_in_bytes(&self) -> usize {
+        self.data.len() + self.offsets.len() * std::mem::size_of::<u64>()
+    }
+
+    pub fn bytes_per_posting(&self) -> f64 {
+        self.size_in_bytes() as f64 / self.num_postings().max(1) as f64
+    }
+}
+
+#[inline]
+pub(super) fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[inline]
+pub(super) fn read_varint(data: &[u8], pos: &mut usize) -> u32 {
+    // Most term id deltas and posting counts fit in a single byte.
+    let byte = data[*pos];
+    if byte < 0x80 {
+        *pos += 1;
+        return byte as u32;
+    }
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte < 0x80 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// Encodes the terms of a block, given by increasing term id with their postings. Each term uses
+/// the smaller of the sparse and dense layouts.
+fn encode_block(terms: &[(u32, Vec<[u8; 2]>)], block_size: usize, out: &mut Vec<u8>) {
+    let mut deltas = Vec::new();
+    let mut headers = Vec::new();
+    let mut postings_data = Vec::new();
+    let mut previous = 0;
+    for (term_id, postings) in terms {
+        let dense = 2 * postings.len() > block_size;
+        write_varint(&mut deltas, term_id - previous);
+        write_varint(&mut headers, (postings.len() as u32) << 1 | dense as u32);
+        if dense {
+            let start = postings_data.len();
+            postings_data.resize(start + block_size, 0);
+            for &[offset, impact] in postings {
+                postings_data[start + offset as usize] = impact;
+            }
+        } else {
+            postings_data.extend(postings.iter().flatten());
+        }
+        previous = *term_id;
+    }
+    write_varint(out, terms.len() as u32);
+    write_varint(out, deltas.len() as u32);
+    write_varint(out, headers.len() as u32);
+    out.extend(deltas);
+    out.extend(headers);
+    out.extend(postings_data);
+}
+
+/// Postings of a term in a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockPostings<'a> {
+    /// `(document offset, impact)` pairs.
+    Sparse(&'a [[u8; 2]]),
+    /// Impact of each document of the block, zero where the term does not appear.
+    Dense(&'a [u8]),
+}
+
+impl<'a> BlockPostings<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            BlockPostings::Sparse(postings) => postings.len(),
+            BlockPostings::Dense(impacts) => impacts.iter().filter(|&&impact| impact > 0).count(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the `(document offset, impact)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, u8)> + 'a {
+        let (sparse, dense): (&[[u8; 2]], &[u8]) = match *self {
+            BlockPostings::Sparse(postings) => (postings, &[]),
+            BlockPostings::Dense(impacts) => (&[], impacts),
+        };
+        sparse
+            .iter()
+            .map(|&[offset, impact]| (offset, impact))
+            .chain(
+                dense
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &impact)| impact > 0)
+                    .map(|(offset, &impact)| (offset as u8, impact)),
+            )
+    }
+}
+
+/// Iterator over the terms of an encoded block.
+pub struct BlockTerms<'a> {
+    data: &'a [u8],
+    block_size: usize,
+    delta_pos: usize,
+    header_pos: usize,
+    postings_pos: usize,
+    remaining: u32,
+    term_id: u32,
+}
+
+impl<'a> BlockTerms<'a> {
+    fn new(data: &'a [u8], block_size: usize) -> Self {
+        let mut pos = 0;
+        let remaining = read_varint(data, &mut pos);
+        let deltas_len = read_varint(data, &mut pos) as usize;
+        let headers_len = read_varint(data, &mut pos) as usize;
+        BlockTerms {
+            data,
+            block_size,
+            delta_pos: pos,
+            header_pos: pos + deltas_len,
+            postings_pos: pos + deltas_len + headers_len,
+            remaining,
+            term_id: 0,
+        }
+    }
+
+    /// Decodes the next term id and postings header, leaving the postings to read or skip.
+    #[inline]
+    fn next_header(&mut self) -> Option<(u32, u32)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.term_id += read_varint(self.data, &mut self.delta_pos);
+        let header = read_varint(self.data, &mut self.header_pos);
+        Some((self.term_id, header))
+    }
+
+    /// Skips the terms preceding `term_id`, a chunk at a time, as long as the chunks are made of
+    /// single-byte varints. The remaining terms preceding `term_id` are left to the caller.
+    #[inline]
+    fn skip_chunks_before(&mut self, term_id: u32) {
+        const CHUNK: usize = simd::SKIP_CHUNK;
+        while self.remaining as usize >= CHUNK && self.term_id < term_id {
+            let deltas = self.data[self.delta_pos..self.delta_pos + CHUNK]
+                .try_into()
+                .unwrap();
+            let headers = self.data[self.header_pos..self.header_pos + CHUNK]
+                .try_into()
+                .unwrap();
+            let Some(skip) =
+                simd::skip_terms(deltas, headers, term_id - self.term_id, self.block_size)
+            else {
+                return;
+            };
+            self.remaining -= skip.terms as u32;
+            self.delta_pos += skip.terms;
+            self.header_pos += skip.terms;
+            self.term_id += skip.delta;
+            self.postings_pos += skip.postings_len;
+            if skip.terms < CHUNK {
+                return;
+            }
+        }
+    }
+
+    #[inline]
+    fn encoded_len(&self, header: u32) -> usize {
+        if header & 1 == 1 {
+            self.block_size
+        } else {
+            2 * (header >> 1) as usize
+        }
+    }
+
+    #[inline]
+    fn skip_postings(&mut self, header: u32) {
+        self.postings_pos += self.encoded_len(header);
+    }
+
+    #[inline]
+    fn postings(&mut self, header: u32) -> BlockPostings<'a> {
+        let data = &self.data[self.postings_pos..self.postings_pos + self.encoded_len(header)];
+        self.postings_pos += data.len();
+        if header & 1 == 1 {
+            BlockPostings::Dense(data)
+        } else {
+            BlockPostings::Sparse(data.as_chunks::<2>().0)
+        }
+    }
+}
+
+impl<'a> Iterator for BlockTerms<'a> {
+    type Item = (u32, BlockPostings<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (term_id, header) = self.next_header()?;
+        Some((term_id, self.postings(header)))
+    }
+}
+
 #[derive(Default, Serialize, Deserialize, Clone)]
 pub struct ForwardIndex {
     data: Vec<Vec<(u32, u32)>>,
 }
+impl ForwardIndex {
+    /// Removes the postings rejected by `filter`. Returns the number of postings kept.
+    pub fn retain_postings(&mut self, filter: &PostingFilter) -> usize {
+        self.data
+            .par_iter_mut()
+            .enumerate()
+            .map(|(doc_id, doc)| {
+                doc.retain(|&(term_id, impact)| filter.keep(term_id, doc_id as u32, impact));
+                doc.len()
+            })
+            .sum()
+    }
+}
+
 pub struct ForwardIndexBuilder {
     forward_index: ForwardIndex,
 }
@@ -51,7 +321,7 @@ impl ForwardIndexBuilder {
     }
     pub fn insert_posting_list(&mut self, term_id: u32, posting_list: &Vec<(u32, u32)>) {
         for (doc_id, score) in posting_list {
-            self.forward_index.data[*doc_id as usize].push((term_id as u32, *score));
+            self.forward_index.data[*doc_id as usize].push((term_id, *score));
         }
     }
     pub fn insert_document(&mut self, vector: Vec<(u32, u32)>) {
@@ -66,85 +336,180 @@ impl ForwardIndexBuilder {
     }
 }
 
+/// Groups the documents of the forward index into blocks of `block_size` documents, with a
+/// progress bar on stderr.
 pub fn fwd2bfwd(fwd: &ForwardIndex, block_size: usize) -> BlockForwardIndex {
+    let progress = indicatif::ProgressBar::new(0);
+    progress.set_style(pb_style());
+    fwd2bfwd_with_progress(fwd, block_size, &progress)
+}
+
+/// Like [`fwd2bfwd`], reporting the progress to `progress`, e.g. a hidden progress bar.
+pub fn fwd2bfwd_with_progress(
+    fwd: &ForwardIndex,
+    block_size: usize,
+    progress: &indicatif::ProgressBar,
+) -> BlockForwardIndex {
     // Step 1: Group documents into blocks
     let blocks = fwd.data.par_chunks(block_size);
-    let progress = indicatif::ProgressBar::new(blocks.len() as u64);
-    progress.set_style(pb_style());
+    progress.set_length(blocks.len() as u64);
     progress.set_draw_delta((blocks.len() / 100) as u64);
 
-    BlockForwardIndex {block_size: block_size, data:
-
-
-    // Step 2: For each block, aggregate term-score pairs
-    blocks
+    // Step 2: For each block, aggregate term-score pairs and encode them
+    let encoded: Vec<Vec<u8>> = blocks
         .map(|block| {
-
-            let mut term_pairs: Vec<(u32, u32, u32)> = block.iter().enumerate().flat_map(|(idx, doc)| {
-                doc.iter().map(move|(term, score)| (*term, idx as u32, *score))
-            }).collect();
+            let mut term_pairs: Vec<(u32, u32, u32)> = block
+                .iter()
+                .enumerate()
+                .flat_map(|(idx, doc)| {
+                    doc.iter()
+                        .map(move |(term, score)| (*term, idx as u32, *score))
+                })
+                .collect();
             // Sort by term to aggregate them in the next step
             term_pairs.sort_by_key(|pair| pair.0);
 
             // Aggregate term-score pairs
-            let mut aggregated: Vec<(u16, Vec<(u8, u8)>)> = Vec::new();
-            let mut current_term = None;
-            let mut current_scores = Vec::new();
-            for (term,doc_id, score) in term_pairs {
-                match current_term {
-                    Some(t) if t == term => current_scores.push((
-                        doc_id as u8,
-                        score as u8,
-                    )),
-                    _ => {
-                        if let Some(t) = current_term {
-                            aggregated.push((t as u16, current_scores.clone()));
-                            current_scores.clear();
-                        }
-                        current_term = Some(term);
-                        current_scores.push((doc_id as u8,score as u8));
-                    }
+            let mut aggregated: Vec<(u32, Vec<[u8; 2]>)> = Vec::new();
+            for (term, doc_id, score) in term_pairs {
+                match aggregated.last_mut() {
+                    Some((t, scores)) if *t == term => scores.push([doc_id as u8, score as u8]),
+                    _ => aggregated.push((term, vec![[doc_id as u8, score as u8]])),
                 }
             }
-            if let Some(t) = current_term {
-                aggregated.push((t as u16, current_scores));
-            }
+            let mut data = Vec::new();
+            encode_block(&aggregated, block_size, &mut data);
             progress.inc(1);
-
-            aggregated
+            data
         })
-        .collect()
+        .collect();
+
+    // Step 3: Concatenate the blocks
+    let mut offsets = Vec::with_capacity(encoded.len() + 1);
+    offsets.push(0);
+    let mut data = Vec::with_capacity(encoded.iter().map(Vec::len).sum());
+    for block in encoded {
+        data.extend_from_slice(&block);
+        offsets.push(data.len() as u64);
+    }
+    BlockForwardIndex {
+        data,
+        offsets,
+        block_size,
     }
 }
 
+/// Scores the documents of an encoded block for a query made of `(term_id, weight)` pairs sorted
+/// by term id.
 #[inline]
-pub fn block_score(
-    query: &Vec<(u16, u8)>,
-    document: &[(u16, Vec<(u8, u8)>)],
-    bsize: usize,
-) -> Vec<u16> {
-    let mut doc_score = vec![0; bsize];
-
-    unsafe {
-        let mut term_ptr = document.as_ptr();
-        let end = term_ptr.wrapping_offset(document.len() as isize);
-        for &(coordinate, value) in query {
-            while term_ptr != end && (*term_ptr).0 < coordinate {
-                term_ptr = term_ptr.add(1);
+pub fn block_score<S: Score>(query: &[(u32, u16)], block: &[u8], bsize: usize) -> Vec<S> {
+    let mut doc_score = vec![S::default(); bsize];
+
+    let mut terms = BlockTerms::new(block, bsize);
+    let mut current = terms.next_header();
+    for &(coordinate, value) in query {
+        if let Some((term_id, header)) = current {
+            if term_id < coordinate {
+                terms.skip_postings(header);
+                terms.skip_chunks_before(coordinate);
+                current = terms.next_header();
             }
-            if term_ptr == end {
+        }
+        while let Some((term_id, header)) = current {
+            if term_id >= coordinate {
                 break;
             }
-            if (*term_ptr).0 == coordinate {
-                let mut inner_ptr = (*term_ptr).1.as_ptr();
-                let end_inner_ptr = inner_ptr.wrapping_offset((*term_ptr).1.len() as isize);
-                while inner_ptr != end_inner_ptr {
-                    doc_score[(*inner_ptr).0 as usize] += (value as u16) * ((*inner_ptr).1 as u16);
-                    inner_ptr = inner_ptr.add(1);
+            terms.skip_postings(header);
+            current = terms.next_header();
+        }
+        let Some((term_id, header)) = current else {
+            break;
+        };
+        if term_id == coordinate {
+            match terms.postings(header) {
+                BlockPostings::Sparse(postings) => {
+                    for &[offset, impact] in postings {
+                        doc_score[offset as usize] += S::product(value, impact);
+                    }
                 }
+                BlockPostings::Dense(impacts) => S::accumulate(&mut doc_score, impacts, value),
             }
+            current = terms.next_header();
         }
     }
 
     doc_score
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_block_encoding() {
+        let mut builder = ForwardIndexBuilder::new(0);
+        builder.insert_document(vec![(3, 1), (300, 2)]);
+        builder.insert_document(vec![(0, 4), (3, 2)]);
+        builder.insert_document(vec![(300, 5), (3, 6)]);
+        builder.insert_document(vec![(7, 1)]);
+        builder.insert_document(vec![(3, 9)]);
+        let bfwd = fwd2bfwd(&builder.build(), 4);
+
+        assert_eq!(bfwd.num_blocks(), 2);
+        assert_eq!(bfwd.num_postings(), 8);
+        let terms: Vec<_> = bfwd.block_terms(0).collect();
+        assert_eq!(
+            terms,
+            vec![
+                (0, BlockPostings::Sparse(&[[1, 4]])),
+                (3, BlockPostings::Dense(&[1, 2, 6, 0])),
+                (7, BlockPostings::Sparse(&[[3, 1]])),
+                (300, BlockPostings::Sparse(&[[0, 2], [2, 5]])),
+            ]
+        );
+        assert_eq!(
+            terms[1].1.iter().collect::<Vec<_>>(),
+            vec![(0, 1), (1, 2), (2, 6)]
+        );
+
+        let query = [(3, 2), (4, 7), (300, 1)];
+        assert_eq!(
+            block_score::<u16>(&query, bfwd.block(0), 4),
+            vec![4, 4, 17, 0]
+        );
+        assert_eq!(
+            block_score::<u16>(&query, bfwd.block(1), 4),
+            vec![18, 0, 0, 0]
+        );
+        assert_eq!(
+            block_score::<u32>(&[(5, 1)], bfwd.block(1), 4),
+            vec![0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_block_score_long_block() {
+        // Enough terms for chunks to be skipped, with some multi-byte term id deltas and a
+        // dense term.
+        let mut builder = ForwardIndexBuilder::new(0);
+        let mut expected = vec![0u32; 8];
+        let query: Vec<(u32, u16)> = (0..2000).step_by(7).map(|t| (t, 3)).collect();
+        for doc in 0..8u32 {
+            let mut vector: Vec<(u32, u32)> = (0..60)
+                .map(|i| {
+                    let term = if i < 50 { i * 13 + doc } else { 1000 + i * 200 };
+                    (term, (doc + i) % 200 + 1)
+                })
+                .collect();
+            vector.push((5000, 7));
+            for &(term, impact) in &vector {
+                if term % 7 == 0 && term < 2000 {
+                    expected[doc as usize] += 3 * impact;
+                }
+            }
+            builder.insert_document(vector);
+        }
+        let bfwd = fwd2bfwd(&builder.build(), 8);
+        assert_eq!(block_score::<u32>(&query, bfwd.block(0), 8), expected);
+    }
+}
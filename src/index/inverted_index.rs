@@ -1,9 +1,15 @@
-use super::posting_list::{BlockData, PostingList, PostingListIterator};
-use fst::{Map, MapBuilder};
+use super::document_lexicon::DocumentLexicon;
+use super::forward_index::{ForwardIndex, ForwardIndexBuilder};
+use super::posting_list::{
+    BlockData, CompressedRangeMaxes, PostingList, PostingListIterator, SuperblockMaxes,
+};
+use super::pruning::PostingFilter;
+use fst::{Automaton, IntoStreamer, Map, MapBuilder, Streamer};
 use num_integer::div_ceil;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::cmp;
+use std::ops::Bound;
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct Index {
@@ -18,11 +24,11 @@ pub struct Index {
     // #[serde(skip_serializing, skip_deserializing)]
     termmap: Map<Vec<u8>>,
     // #[serde(skip_serializing, skip_deserializing)]
-    documents: Vec<String>,
+    documents: DocumentLexicon,
 }
 
 // Serialization function for the FST Map
-fn serialize_fst_map<S>(termmap: &Map<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+pub(super) fn serialize_fst_map<S>(termmap: &Map<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
@@ -31,7 +37,7 @@ where
 }
 
 // Deserialization function for the FST Map
-fn deserialize_fst_map<'de, D>(deserializer: D) -> Result<Map<Vec<u8>>, D::Error>
+pub(super) fn deserialize_fst_map<'de, D>(deserializer: D) -> Result<Map<Vec<u8>>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
@@ -46,10 +52,10 @@ impl Index {
             num_documents,
             posting_lists: Vec::new(),
             termmap: Map::default(),
-            documents: Vec::new(),
+            documents: DocumentLexicon::default(),
         }
     }
-    pub fn documents(&self) -> &Vec<String> {
+    pub fn documents(&self) -> &DocumentLexicon {
         &self.documents
     }
 
@@ -61,11 +67,78 @@ impl Index {
         self.num_documents
     }
 
-    pub fn get_cursor(&self, term: &str, term_weight: u32) -> Option<PostingListIterator> {
+    pub fn get_cursor(&self, term: &str, term_weight: u32) -> Option<PostingListIterator<'_>> {
         self.termmap.get(term).map(|position| {
             self.posting_lists[position as usize].iter(position as u32, term_weight)
         })
     }
+
+    pub fn num_terms(&self) -> usize {
+        self.posting_lists.len()
+    }
+
+    /// Terms of the index, by term id.
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms = vec![String::new(); self.num_terms()];
+        let mut stream = self.termmap.stream();
+        while let Some((term, position)) = stream.next() {
+            terms[position as usize] = String::from_utf8_lossy(term).into_owned();
+        }
+        terms
+    }
+
+    /// Calls `f` with each term of the index accepted by `automaton`, in lexicographic order, and
+    /// its posting list.
+    pub fn for_each_term<A: Automaton>(&self, automaton: A, f: impl FnMut(&str, &PostingList)) {
+        self.for_each_streamed_term(self.termmap.search(automaton), f)
+    }
+
+    /// Calls `f` with each term of the index between `lower` and `upper`, in lexicographic order,
+    /// and its posting list.
+    pub fn for_each_term_in_range(
+        &self,
+        lower: Bound<&str>,
+        upper: Bound<&str>,
+        f: impl FnMut(&str, &PostingList),
+    ) {
+        let mut range = self.termmap.range();
+        range = match lower {
+            Bound::Included(term) => range.ge(term),
+            Bound::Excluded(term) => range.gt(term),
+            Bound::Unbounded => range,
+        };
+        range = match upper {
+            Bound::Included(term) => range.le(term),
+            Bound::Excluded(term) => range.lt(term),
+            Bound::Unbounded => range,
+        };
+        self.for_each_streamed_term(range, f)
+    }
+
+    fn for_each_streamed_term<A: Automaton>(
+        &self,
+        terms: fst::map::StreamBuilder<'_, A>,
+        mut f: impl FnMut(&str, &PostingList),
+    ) {
+        let mut stream = terms.into_stream();
+        while let Some((term, position)) = stream.next() {
+            f(
+                &String::from_utf8_lossy(term),
+                &self.posting_lists[position as usize],
+            );
+        }
+    }
+
+    pub fn posting_list(&self, term: &str) -> Option<&PostingList> {
+        self.termmap
+            .get(term)
+            .map(|position| &self.posting_lists[position as usize])
+    }
+
+    /// Whether the range maxes of the posting lists are compressed.
+    pub fn compressed_ranges(&self) -> bool {
+        self.posting_lists.iter().any(PostingList::is_compressed)
+    }
 }
 
 #[derive(Default)]
@@ -101,35 +174,56 @@ impl IndexBuilder {
     pub fn insert_document(&mut self, name: &str) -> u32 {
         let doc_id = self.documents.len();
         self.documents.push(name.to_string());
-        return doc_id as u32;
+        doc_id as u32
     }
 
-    fn compress(data: &[u8]) -> Vec<crate::index::posting_list::CompressedBlock> {
-        let mut compressed = Vec::new();
-
-        for superblock in data.chunks(256) {
-            let mut max_scores = Vec::new();
+    pub fn num_documents(&self) -> usize {
+        if self.num_documents == 0 {
+            self.documents.len()
+        } else {
+            self.num_documents
+        }
+    }
 
-            for (offset, &value) in superblock.iter().enumerate() {
-                if value > 0 {
-                    max_scores.push((offset, value));
-                }
-            }
+    /// Posting lists inserted so far, as `(doc_id, impact)` pairs indexed by term id.
+    pub fn posting_lists(&self) -> &[Vec<(u32, u32)>] {
+        &self.posting_lists
+    }
 
-            compressed.push(crate::index::posting_list::CompressedBlock { max_scores });
-        }
+    /// Removes the postings rejected by `filter`. Returns the number of postings kept.
+    pub fn retain_postings(&mut self, filter: &PostingFilter) -> usize {
+        self.posting_lists
+            .par_iter_mut()
+            .enumerate()
+            .map(|(term_id, list)| {
+                list.retain(|&(doc_id, impact)| filter.keep(term_id as u32, doc_id, impact));
+                list.len()
+            })
+            .sum()
+    }
 
-        compressed
+    pub fn build(mut self, compress_range: bool) -> Index {
+        let posting_lists = std::mem::take(&mut self.posting_lists);
+        self.build_index(&posting_lists, compress_range)
     }
 
-    pub fn build(self, compress_range: bool) -> Index {
-        let mut num_docs = self.num_documents;
-        if num_docs == 0 {
-            num_docs = self.documents.len();
+    /// Builds the index and the forward index of the same postings. Each posting list is dropped
+    /// once added to the forward index, so that the postings are not held twice.
+    pub fn build_with_forward_index(mut self, compress_range: bool) -> (Index, ForwardIndex) {
+        let posting_lists = std::mem::take(&mut self.posting_lists);
+        let num_docs = self.num_documents();
+        let index = self.build_index(&posting_lists, compress_range);
+        let mut fwd_builder = ForwardIndexBuilder::new(num_docs);
+        for (term_id, posting_list) in posting_lists.into_iter().enumerate() {
+            fwd_builder.insert_posting_list(term_id as u32, &posting_list);
         }
-        let posting_lists: Vec<PostingList> = self
-            .posting_lists
-            .into_par_iter()
+        (index, fwd_builder.build())
+    }
+
+    fn build_index(self, posting_lists: &[Vec<(u32, u32)>], compress_range: bool) -> Index {
+        let num_docs = self.num_documents();
+        let posting_lists: Vec<PostingList> = posting_lists
+            .par_iter()
             .map(|p_list| {
                 let range_size = self.bsize;
                 let blocks_num = div_ceil(num_docs, range_size);
@@ -139,21 +233,26 @@ impl IndexBuilder {
                     *current_max = cmp::max(*current_max, score as u8);
                 });
                 let mut sorted_scores: Vec<u32> = p_list.iter().map(|&(_, score)| score).collect();
-                sorted_scores.sort_by(|a, b| b.cmp(&a));
+                sorted_scores.sort_by(|a, b| b.cmp(a));
 
                 // Retrieve the 10th, 100th and 1000th elements
                 let s10th = sorted_scores.get(9).copied().unwrap_or(0) as u8;
                 let s100th = sorted_scores.get(99).copied().unwrap_or(0) as u8;
                 let s1000th = sorted_scores.get(999).copied().unwrap_or(0) as u8;
+                let max_impact = sorted_scores.first().copied().unwrap_or(0) as u8;
 
+                let superblock_maxes = SuperblockMaxes::new(&range_maxes);
                 PostingList::new(
                     // p_list,
                     // max_score as f32,
                     match compress_range {
-                        true => BlockData::Compressed(Self::compress(&range_maxes)),
+                        true => BlockData::Compressed(CompressedRangeMaxes::new(&range_maxes)),
                         false => BlockData::Raw(range_maxes),
                     },
+                    superblock_maxes,
                     vec![s10th, s100th, s1000th],
+                    p_list.len() as u32,
+                    max_impact,
                 )
             })
             .collect();
@@ -173,7 +272,31 @@ impl IndexBuilder {
             num_documents: num_docs,
             posting_lists,
             termmap: Map::new(build.into_inner().unwrap()).unwrap(),
-            documents: self.documents,
+            documents: DocumentLexicon::new(&self.documents),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_index_statistics() {
+        let mut builder = IndexBuilder::new(0, 2);
+        for name in ["d0", "d1", "d2"] {
+            builder.insert_document(name);
         }
+        builder.insert_term("zebra", vec![(0, 3), (2, 7)]);
+        builder.insert_term("apple", vec![(1, 1)]);
+        let index = builder.build(true);
+
+        assert_eq!(index.num_documents(), 3);
+        assert_eq!(index.num_terms(), 2);
+        assert_eq!(index.terms(), vec!["zebra", "apple"]);
+        assert!(index.compressed_ranges());
+        let zebra = index.posting_list("zebra").unwrap();
+        assert_eq!((zebra.doc_freq(), zebra.max_impact()), (2, 7));
+        assert!(index.posting_list("mango").is_none());
     }
 }
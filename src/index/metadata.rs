@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Query-independent metadata of the documents, e.g. their title, URL or length, as JSON objects.
+///
+/// The objects are concatenated into a single string, so that storing them costs little more than
+/// their text. Documents without metadata have an empty entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentMetadata {
+    data: String,
+    offsets: Vec<usize>,
+}
+
+impl Default for DocumentMetadata {
+    fn default() -> Self {
+        DocumentMetadata {
+            data: String::new(),
+            offsets: vec![0],
+        }
+    }
+}
+
+impl DocumentMetadata {
+    /// Appends the metadata of the next document, a JSON object, or `None` if it has none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `metadata` is not a JSON object.
+    pub fn push(&mut self, metadata: Option<&str>) -> Result<()> {
+        if let Some(metadata) = metadata {
+            Self::check(metadata)?;
+            self.data.push_str(metadata);
+        }
+        self.offsets.push(self.data.len());
+        Ok(())
+    }
+
+    /// Checks that `metadata` can be pushed, i.e. that it is a JSON object.
+    pub fn check(metadata: &str) -> Result<()> {
+        serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(metadata)
+            .with_context(|| format!("Document metadata is not a JSON object: {}", metadata))?;
+        Ok(())
+    }
+
+    /// Number of documents.
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Metadata of document `doc_id`, as a JSON object, or `None` if it has none.
+    pub fn get(&self, doc_id: u32) -> Option<&str> {
+        let doc_id = doc_id as usize;
+        if doc_id >= self.len() {
+            return None;
+        }
+        let metadata = &self.data[self.offsets[doc_id]..self.offsets[doc_id + 1]];
+        (!metadata.is_empty()).then_some(metadata)
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        self.data.len() + self.offsets.len() * std::mem::size_of::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_document_metadata() {
+        let mut metadata = DocumentMetadata::default();
+        metadata.push(Some(r#"{"title": "a"}"#)).unwrap();
+        metadata.push(None).unwrap();
+        metadata
+            .push(Some(r#"{"url": "http://b", "length": 3}"#))
+            .unwrap();
+        assert!(metadata.push(Some("[1, 2]")).is_err());
+        assert!(metadata.push(Some("{")).is_err());
+        assert!(DocumentMetadata::check("{").is_err());
+
+        assert_eq!(metadata.len(), 3);
+        assert_eq!(metadata.get(0), Some(r#"{"title": "a"}"#));
+        assert_eq!(metadata.get(1), None);
+        assert_eq!(metadata.get(2), Some(r#"{"url": "http://b", "length": 3}"#));
+        assert_eq!(metadata.get(3), None);
+    }
+}
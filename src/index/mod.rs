@@ -1,16 +1,242 @@
+pub mod document_lexicon;
 pub mod forward_index;
 pub mod inverted_index;
+pub mod metadata;
 pub mod posting_list;
+pub mod pruning;
+pub mod simd;
+pub mod writer;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use forward_index::BlockForwardIndex;
+use inverted_index::Index;
+use metadata::DocumentMetadata;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::PathBuf;
 
-pub fn from_file<P: Into<PathBuf>>(
-    index_path: P,
-) -> Result<(inverted_index::Index, forward_index::BlockForwardIndex)> {
-    let file = File::open(index_path.into())?;
-    let reader = BufReader::new(file);
-    Ok(bincode::deserialize_from(reader)?)
+/// Bytes with which index files start, followed by their format version.
+const MAGIC: &[u8; 4] = b"BMPI";
+
+/// Version of the index format, increased whenever the layout of the index files changes. Files of
+/// another version cannot be read, and must be rebuilt.
+///
+/// - v1: first versioned format. Index files written before it have no header and are rejected:
+///   their layout differs in any case, as posting lists now store the document frequency, the
+///   largest impact and the superblock maxes of their term, and document names are front-coded.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A BMP index: the inverted index, giving the block upper bounds of each term, and the block
+/// forward index, with which the documents of the selected blocks are scored, and optionally the
+/// metadata of the documents.
+#[derive(Default)]
+pub struct BmpIndex {
+    inverted_index: Index,
+    forward_index: BlockForwardIndex,
+    metadata: Option<DocumentMetadata>,
+}
+
+impl BmpIndex {
+    pub fn new(inverted_index: Index, forward_index: BlockForwardIndex) -> Self {
+        BmpIndex {
+            inverted_index,
+            forward_index,
+            metadata: None,
+        }
+    }
+
+    /// Sets the metadata of the documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `metadata` does not have an entry per document.
+    pub fn with_metadata(mut self, metadata: DocumentMetadata) -> Result<Self> {
+        let num_documents = self.inverted_index.num_documents();
+        if metadata.len() != num_documents {
+            anyhow::bail!(
+                "Metadata of {} documents for an index of {} documents",
+                metadata.len(),
+                num_documents
+            );
+        }
+        self.metadata = Some(metadata);
+        Ok(self)
+    }
+
+    /// Reads an index written by [`BmpIndex::write_to`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, or is not an index of the current
+    /// [`FORMAT_VERSION`].
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let file =
+            File::open(&path).with_context(|| format!("Unable to open {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+        check_header(&mut reader)
+            .with_context(|| format!("Unable to read the index {}", path.display()))?;
+        let read = |reader: &mut BufReader<File>| -> Result<Self> {
+            let inverted_index = bincode::deserialize_from(&mut *reader)?;
+            let forward_index = bincode::deserialize_from(&mut *reader)?;
+            // The metadata is written after the two parts only when present, so that indexes
+            // without metadata keep the same format.
+            let metadata = if reader.fill_buf()?.is_empty() {
+                None
+            } else {
+                Some(bincode::deserialize_from(&mut *reader)?)
+            };
+            Ok(BmpIndex {
+                inverted_index,
+                forward_index,
+                metadata,
+            })
+        };
+        read(&mut reader).with_context(|| format!("Unable to read the index {}", path.display()))
+    }
+
+    pub fn write_to<P: Into<PathBuf>>(&self, path: P) -> Result<()> {
+        let path = path.into();
+        let file =
+            File::create(&path).with_context(|| format!("Unable to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        let write = |writer: &mut BufWriter<File>| -> Result<()> {
+            writer.write_all(MAGIC)?;
+            writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+            bincode::serialize_into(&mut *writer, &self.inverted_index)?;
+            bincode::serialize_into(&mut *writer, &self.forward_index)?;
+            if let Some(metadata) = &self.metadata {
+                bincode::serialize_into(&mut *writer, metadata)?;
+            }
+            writer.flush()?;
+            Ok(())
+        };
+        write(&mut writer).with_context(|| format!("Unable to write the index {}", path.display()))
+    }
+
+    pub fn inverted_index(&self) -> &Index {
+        &self.inverted_index
+    }
+
+    pub fn forward_index(&self) -> &BlockForwardIndex {
+        &self.forward_index
+    }
+
+    pub fn metadata(&self) -> Option<&DocumentMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Metadata of document `doc_id`, as a JSON object, or `None` if it has none.
+    pub fn document_metadata(&self, doc_id: u32) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(doc_id))
+    }
+
+    /// Removes the metadata, e.g. to keep it apart from the parts returned by
+    /// [`BmpIndex::into_parts`].
+    pub fn take_metadata(&mut self) -> Option<DocumentMetadata> {
+        self.metadata.take()
+    }
+
+    pub fn into_parts(self) -> (Index, BlockForwardIndex) {
+        (self.inverted_index, self.forward_index)
+    }
+}
+
+/// Reads the magic bytes and the format version of an index file, and checks that it can be read.
+fn check_header(reader: &mut impl Read) -> Result<()> {
+    let mut header = Vec::with_capacity(8);
+    reader.take(8).read_to_end(&mut header)?;
+    let rebuild = "rebuild it with ciff2bmp or jsonl2bmp";
+    if header.len() < 8 || &header[..4] != MAGIC {
+        anyhow::bail!(
+            "Not a BMP index of format v{} (written by an older version?); {}",
+            FORMAT_VERSION,
+            rebuild
+        );
+    }
+    let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+    if version != FORMAT_VERSION {
+        anyhow::bail!(
+            "BMP index of format v{}, but only format v{} can be read; {}",
+            version,
+            FORMAT_VERSION,
+            rebuild
+        );
+    }
+    Ok(())
+}
+
+/// Reads the two parts of an index written by [`BmpIndex::write_to`].
+pub fn from_file<P: Into<PathBuf>>(index_path: P) -> Result<(Index, BlockForwardIndex)> {
+    BmpIndex::open(index_path).map(BmpIndex::into_parts)
+}
+
+/// Impact of a document term with float `weight`, truncated after scaling, and at most 255.
+/// Non-positive (or NaN) weights have no impact.
+pub fn quantize_impact(weight: f32, scale: f32) -> u32 {
+    if weight > 0.0 {
+        (weight * scale).min(u8::MAX as f32) as u32
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::writer::IndexWriter;
+    use super::*;
+
+    #[test]
+    fn test_write_and_open() {
+        let path = std::env::temp_dir().join(format!("bmp-test-{}.bmp", std::process::id()));
+        for with_metadata in [false, true] {
+            let mut writer = IndexWriter::new(2);
+            writer.quiet(true);
+            let a = writer.term_id("a");
+            writer.add_document("d0", &[(a, 3)]).unwrap();
+            writer.add_document("d1", &[(a, 1)]).unwrap();
+            if with_metadata {
+                writer.set_metadata(1, r#"{"url": "http://d1"}"#).unwrap();
+            }
+            writer.finish().unwrap().write_to(&path).unwrap();
+
+            let index = BmpIndex::open(&path).unwrap();
+            assert_eq!(index.inverted_index().documents().id("d1"), Some(1));
+            assert_eq!(index.forward_index().num_blocks(), 1);
+            assert_eq!(index.metadata().is_some(), with_metadata);
+            assert_eq!(index.document_metadata(0), None);
+            assert_eq!(
+                index.document_metadata(1),
+                with_metadata.then_some(r#"{"url": "http://d1"}"#)
+            );
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let metadata = DocumentMetadata::default();
+        assert!(BmpIndex::default().with_metadata(metadata).is_ok());
+        let mut metadata = DocumentMetadata::default();
+        metadata.push(None).unwrap();
+        assert!(BmpIndex::default().with_metadata(metadata).is_err());
+    }
+
+    #[test]
+    fn test_format_version() {
+        let path = std::env::temp_dir().join(format!("bmp-test-{}.version", std::process::id()));
+        let open_error = |contents: &[u8]| {
+            std::fs::write(&path, contents).unwrap();
+            format!("{:#}", BmpIndex::open(&path).err().unwrap())
+        };
+        // An index written before the format version.
+        let old_index = bincode::serialize(&Index::default()).unwrap();
+        assert!(open_error(&old_index).contains(
+            "Not a BMP index of format v1 (written by an older version?); rebuild it with ciff2bmp or jsonl2bmp"
+        ));
+        assert!(open_error(b"BMP").contains("Not a BMP index"));
+        let newer_index = [&MAGIC[..], &2u32.to_le_bytes()].concat();
+        assert!(open_error(&newer_index)
+            .contains("BMP index of format v2, but only format v1 can be read"));
+        std::fs::remove_file(&path).unwrap();
+    }
 }
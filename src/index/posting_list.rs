@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum BlockData {
-    Compressed(Vec<CompressedBlock>),
+    Compressed(CompressedRangeMaxes),
     Raw(Vec<u8>),
 }
 
@@ -13,25 +13,178 @@ impl Default for BlockData {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CompressedBlock {
-    pub max_scores: Vec<(usize, u8)>, // pairs of offset and max score
+/// Number of blocks in a superblock of [`CompressedRangeMaxes`].
+pub const SUPERBLOCK_SIZE: usize = 256;
+
+/// Range maxes of a posting list, grouped into superblocks of [`SUPERBLOCK_SIZE`] blocks.
+///
+/// Only the superblocks where the term appears are stored, each with a bitmap of the blocks it
+/// stores and their range maxes in order. When the term appears in more than a quarter of the
+/// blocks of a superblock, all of them are stored, zeros included, so that they can be added with
+/// vector instructions, which is faster than visiting them one by one.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CompressedRangeMaxes {
+    superblocks: Vec<u32>,
+    bitmaps: Vec<[u64; SUPERBLOCK_SIZE / 64]>,
+    /// Start of the values of each superblock, followed by the end of the last one.
+    offsets: Vec<u32>,
+    values: Vec<u8>,
+}
+
+/// Stored superblock of [`CompressedRangeMaxes`].
+#[derive(Debug, Clone, Copy)]
+pub struct CompressedSuperblock<'a> {
+    /// Index of the superblock, whose first block is `id * SUPERBLOCK_SIZE`.
+    pub id: usize,
+    pub bitmap: &'a [u64; SUPERBLOCK_SIZE / 64],
+    /// Range maxes of the blocks set in `bitmap`.
+    pub values: &'a [u8],
+}
+
+impl CompressedRangeMaxes {
+    pub fn new(range_maxes: &[u8]) -> Self {
+        let mut compressed = CompressedRangeMaxes {
+            offsets: vec![0],
+            ..Default::default()
+        };
+        for (id, superblock) in range_maxes.chunks(SUPERBLOCK_SIZE).enumerate() {
+            let nonzero = superblock.iter().filter(|&&value| value > 0).count();
+            if nonzero == 0 {
+                continue;
+            }
+            let dense = 4 * nonzero > superblock.len();
+            let mut bitmap = [0; SUPERBLOCK_SIZE / 64];
+            for (offset, &value) in superblock.iter().enumerate() {
+                if dense || value > 0 {
+                    bitmap[offset / 64] |= 1 << (offset % 64);
+                    compressed.values.push(value);
+                }
+            }
+            compressed.superblocks.push(id as u32);
+            compressed.bitmaps.push(bitmap);
+            compressed.offsets.push(compressed.values.len() as u32);
+        }
+        compressed
+    }
+
+    /// Number of stored superblocks.
+    pub fn len(&self) -> usize {
+        self.superblocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.superblocks.is_empty()
+    }
+
+    /// Position of the first stored superblock at or after `pos` whose id is at least `id`.
+    ///
+    /// Gallops from `pos`, since the superblock sought is usually close.
+    #[inline]
+    pub fn seek(&self, mut pos: usize, id: usize) -> usize {
+        let before = |pos: usize| {
+            self.superblocks
+                .get(pos)
+                .is_some_and(|&sb| (sb as usize) < id)
+        };
+        if !before(pos) {
+            return pos;
+        }
+        let mut step = 1;
+        while before(pos + step) {
+            pos += step;
+            step *= 2;
+        }
+        let end = self.superblocks.len().min(pos + step);
+        pos + 1 + self.superblocks[pos + 1..end].partition_point(|&sb| (sb as usize) < id)
+    }
+
+    /// Stored superblock at position `pos`.
+    #[inline]
+    pub fn get(&self, pos: usize) -> Option<CompressedSuperblock<'_>> {
+        Some(CompressedSuperblock {
+            id: *self.superblocks.get(pos)? as usize,
+            bitmap: &self.bitmaps[pos],
+            values: &self.values[self.offsets[pos] as usize..self.offsets[pos + 1] as usize],
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = CompressedSuperblock<'_>> {
+        (0..self.len()).filter_map(|pos| self.get(pos))
+    }
 }
 
+/// Largest range max of each superblock of [`SUPERBLOCK_SIZE`] blocks where a term appears, used
+/// to discard whole superblocks before computing the upper bounds of their blocks.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SuperblockMaxes {
+    superblocks: Vec<u32>,
+    maxes: Vec<u8>,
+}
+
+impl SuperblockMaxes {
+    pub fn new(range_maxes: &[u8]) -> Self {
+        let mut superblock_maxes = SuperblockMaxes::default();
+        for (id, superblock) in range_maxes.chunks(SUPERBLOCK_SIZE).enumerate() {
+            let max = superblock.iter().copied().max().unwrap_or(0);
+            if max > 0 {
+                superblock_maxes.superblocks.push(id as u32);
+                superblock_maxes.maxes.push(max);
+            }
+        }
+        superblock_maxes
+    }
+
+    /// Iterates over the superblocks where the term appears and their maxes.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        self.superblocks
+            .iter()
+            .zip(&self.maxes)
+            .map(|(&id, &max)| (id as usize, max))
+    }
+}
+
+/// Block upper bounds and statistics of a term, as stored in the index: changing its fields changes
+/// the index format, whose [`FORMAT_VERSION`](super::FORMAT_VERSION) must be increased.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PostingList {
     range_maxes: BlockData,
+    superblock_maxes: SuperblockMaxes,
     kth_score: Vec<u8>,
+    doc_freq: u32,
+    max_impact: u8,
 }
 
 impl PostingList {
-    pub fn new(range_maxes: BlockData, kth_score: Vec<u8>) -> Self {
+    pub fn new(
+        range_maxes: BlockData,
+        superblock_maxes: SuperblockMaxes,
+        kth_score: Vec<u8>,
+        doc_freq: u32,
+        max_impact: u8,
+    ) -> Self {
         PostingList {
             range_maxes,
+            superblock_maxes,
             kth_score,
+            doc_freq,
+            max_impact,
         }
     }
 
+    /// Number of documents containing the term.
+    pub fn doc_freq(&self) -> u32 {
+        self.doc_freq
+    }
+
+    /// Largest impact of the term in any document.
+    pub fn max_impact(&self) -> u8 {
+        self.max_impact
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self.range_maxes, BlockData::Compressed(_))
+    }
+
     pub fn kth(&self, k: usize) -> u8 {
         let idx = ((k > 10) as usize) + (k > 100) as usize + (k > 1000) as usize;
         self.kth_score.get(idx).copied().unwrap_or(0)
@@ -47,31 +200,40 @@ pub struct PostingListIterator<'a> {
     posting_list: &'a PostingList,
     current: usize,
     term_id: u32,
-    term_weight: u32,
+    term_weight: u16,
 }
 
 impl<'a> PostingListIterator<'a> {
+    /// Iterator of `posting_list`, whose `term_weight` saturates at `u16::MAX`.
     pub fn new(posting_list: &'a PostingList, term_id: u32, term_weight: u32) -> Self {
         PostingListIterator {
             posting_list,
             current: usize::MAX,
             term_id,
-            term_weight,
+            term_weight: term_weight.min(u16::MAX as u32) as u16,
         }
     }
 
     pub fn kth(&self, k: usize) -> u8 {
-        return self.posting_list.kth(k);
+        self.posting_list.kth(k)
     }
 
-    pub fn term_weight(&self) -> u8 {
-        self.term_weight as u8
+    pub fn term_weight(&self) -> u16 {
+        self.term_weight
     }
 
     pub fn term_id(&self) -> u32 {
         self.term_id
     }
 
+    pub fn doc_freq(&self) -> u32 {
+        self.posting_list.doc_freq()
+    }
+
+    pub fn max_impact(&self) -> u8 {
+        self.posting_list.max_impact()
+    }
+
     pub fn position(&self) -> usize {
         self.current
     }
@@ -88,10 +250,14 @@ impl<'a> Cursor for PostingListIterator<'a> {
 }
 
 impl<'a> RangeMaxScoreCursor for PostingListIterator<'a> {
-    fn range_max_scores(&self) -> RangeMaxScore {
+    fn range_max_scores(&self) -> RangeMaxScore<'_> {
         match &self.posting_list.range_maxes {
             BlockData::Compressed(compressed_block) => RangeMaxScore::Compressed(compressed_block),
             BlockData::Raw(raw_bytes) => RangeMaxScore::Raw(raw_bytes),
         }
     }
+
+    fn superblock_maxes(&self) -> &SuperblockMaxes {
+        &self.posting_list.superblock_maxes
+    }
 }
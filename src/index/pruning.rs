@@ -0,0 +1,268 @@
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use structopt::StructOpt;
+
+/// `(impact, Reverse(id))` pair ordering the postings of a posting list or document, the
+/// smallest id winning ties.
+type Key = (u32, Reverse<u32>);
+
+/// Static pruning of the postings at indexing time.
+///
+/// Policies are applied in this order: postings below the impact threshold are dropped, then
+/// each posting list keeps its highest-impact fraction, and finally each document keeps its
+/// highest-impact terms. Ties are broken in favor of the smallest document or term id, so that
+/// the outcome does not depend on the order in which postings are visited.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StaticPruning {
+    min_impact: Option<u32>,
+    posting_list_fraction: Option<f32>,
+    terms_per_document: Option<usize>,
+}
+
+impl StaticPruning {
+    /// Drops the postings whose impact is below `min_impact`.
+    pub fn min_impact(&mut self, min_impact: u32) -> &mut Self {
+        self.min_impact = Some(min_impact);
+        self
+    }
+
+    /// Keeps the `ceil(len * fraction)` highest-impact postings of each posting list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fraction` is not in `[0, 1]`.
+    pub fn posting_list_fraction(&mut self, fraction: f32) -> &mut Self {
+        assert!(
+            (0.0..=1.0).contains(&fraction),
+            "posting list fraction must be in [0, 1]"
+        );
+        self.posting_list_fraction = Some(fraction);
+        self
+    }
+
+    /// Keeps the `terms` highest-impact terms of each document.
+    pub fn terms_per_document(&mut self, terms: usize) -> &mut Self {
+        self.terms_per_document = Some(terms);
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// Computes the filter selecting the postings to keep among `posting_lists`, where the
+    /// posting list of term `i` holds `(doc_id, impact)` pairs.
+    pub fn filter(&self, posting_lists: &[Vec<(u32, u32)>], num_documents: usize) -> PostingFilter {
+        let mut filter = PostingFilter {
+            min_impact: self.min_impact.unwrap_or(0),
+            term_thresholds: Vec::new(),
+            doc_thresholds: Vec::new(),
+        };
+
+        if let Some(fraction) = self.posting_list_fraction {
+            filter.term_thresholds = posting_lists
+                .par_iter()
+                .map(|list| {
+                    let mut keys: Vec<_> = list
+                        .iter()
+                        .filter(|&&(_, impact)| impact >= filter.min_impact)
+                        .map(|&(doc_id, impact)| (impact, Reverse(doc_id)))
+                        .collect();
+                    let to_keep = (keys.len() as f32 * fraction).ceil() as usize;
+                    if to_keep == 0 {
+                        return (u32::MAX, Reverse(0));
+                    }
+                    if to_keep >= keys.len() {
+                        return (0, Reverse(u32::MAX));
+                    }
+                    *keys.select_nth_unstable_by(to_keep - 1, |a, b| b.cmp(a)).1
+                })
+                .collect();
+        }
+
+        if let Some(terms) = self.terms_per_document {
+            // Min-heaps holding the `terms` largest keys of each document.
+            let mut heaps: Vec<BinaryHeap<Reverse<Key>>> = vec![BinaryHeap::new(); num_documents];
+            for (term_id, list) in posting_lists.iter().enumerate() {
+                for &(doc_id, impact) in list {
+                    if !filter.keep_in_list(term_id as u32, doc_id, impact) {
+                        continue;
+                    }
+                    let key = (impact, Reverse(term_id as u32));
+                    let heap = &mut heaps[doc_id as usize];
+                    if heap.len() < terms {
+                        heap.push(Reverse(key));
+                    } else if heap.peek().is_some_and(|min| key > min.0) {
+                        heap.pop();
+                        heap.push(Reverse(key));
+                    }
+                }
+            }
+            filter.doc_thresholds = heaps
+                .into_par_iter()
+                .map(|heap| match heap.peek() {
+                    Some(min) if heap.len() >= terms => min.0,
+                    _ if terms == 0 => (u32::MAX, Reverse(0)),
+                    _ => (0, Reverse(u32::MAX)),
+                })
+                .collect();
+        }
+        filter
+    }
+}
+
+/// Command-line options of a [`StaticPruning`], shared by the indexing binaries through
+/// `#[structopt(flatten)]`.
+#[derive(Debug, Default, Clone, StructOpt)]
+pub struct StaticPruningArgs {
+    #[structopt(long, help = "Drop the postings whose impact is below this value")]
+    min_impact: Option<u32>,
+    #[structopt(
+        long,
+        parse(try_from_str = parse_fraction),
+        help = "Keep the given fraction of each posting list, by impact"
+    )]
+    posting_list_fraction: Option<f32>,
+    #[structopt(
+        long,
+        help = "Keep the given number of terms of each document, by impact"
+    )]
+    terms_per_document: Option<usize>,
+}
+
+impl StaticPruningArgs {
+    pub fn pruning(&self) -> StaticPruning {
+        let mut pruning = StaticPruning::default();
+        if let Some(min_impact) = self.min_impact {
+            pruning.min_impact(min_impact);
+        }
+        if let Some(fraction) = self.posting_list_fraction {
+            pruning.posting_list_fraction(fraction);
+        }
+        if let Some(terms) = self.terms_per_document {
+            pruning.terms_per_document(terms);
+        }
+        pruning
+    }
+}
+
+fn parse_fraction(s: &str) -> Result<f32, String> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|f| (0.0..=1.0).contains(f))
+        .ok_or_else(|| format!("invalid fraction: {} (expected a value in [0, 1])", s))
+}
+
+/// Selects the postings surviving a [`StaticPruning`].
+///
+/// The decision only depends on the posting itself, so the same filter can be applied to the
+/// inverted and forward representations of the collection.
+#[derive(Debug, Clone)]
+pub struct PostingFilter {
+    min_impact: u32,
+    /// Smallest `(impact, Reverse(doc_id))` kept in each posting list.
+    term_thresholds: Vec<Key>,
+    /// Smallest `(impact, Reverse(term_id))` kept in each document.
+    doc_thresholds: Vec<Key>,
+}
+
+impl PostingFilter {
+    #[inline]
+    fn keep_in_list(&self, term_id: u32, doc_id: u32, impact: u32) -> bool {
+        impact >= self.min_impact
+            && self
+                .term_thresholds
+                .get(term_id as usize)
+                .is_none_or(|&threshold| (impact, Reverse(doc_id)) >= threshold)
+    }
+
+    /// Whether the posting of `term_id` in `doc_id` with the given impact is kept.
+    #[inline]
+    pub fn keep(&self, term_id: u32, doc_id: u32, impact: u32) -> bool {
+        self.keep_in_list(term_id, doc_id, impact)
+            && self
+                .doc_thresholds
+                .get(doc_id as usize)
+                .is_none_or(|&threshold| (impact, Reverse(term_id)) >= threshold)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn prune(pruning: &StaticPruning, lists: &[Vec<(u32, u32)>]) -> Vec<Vec<(u32, u32)>> {
+        let filter = pruning.filter(lists, 4);
+        lists
+            .iter()
+            .enumerate()
+            .map(|(term_id, list)| {
+                list.iter()
+                    .copied()
+                    .filter(|&(doc_id, impact)| filter.keep(term_id as u32, doc_id, impact))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_static_pruning() {
+        let lists = vec![
+            vec![(0, 5), (1, 1), (2, 3), (3, 3)],
+            vec![(0, 2), (2, 7)],
+            vec![(0, 5), (1, 4)],
+        ];
+        assert!(!StaticPruning::default().is_enabled());
+        assert_eq!(prune(&StaticPruning::default(), &lists), lists);
+        assert_eq!(
+            prune(StaticPruning::default().min_impact(3), &lists),
+            vec![
+                vec![(0, 5), (2, 3), (3, 3)],
+                vec![(2, 7)],
+                vec![(0, 5), (1, 4)]
+            ]
+        );
+        assert_eq!(
+            prune(StaticPruning::default().posting_list_fraction(0.5), &lists),
+            vec![vec![(0, 5), (2, 3)], vec![(2, 7)], vec![(0, 5)]]
+        );
+        assert_eq!(
+            prune(StaticPruning::default().terms_per_document(1), &lists),
+            vec![vec![(0, 5), (3, 3)], vec![(2, 7)], vec![(1, 4)]]
+        );
+        assert_eq!(
+            prune(
+                StaticPruning::default()
+                    .min_impact(2)
+                    .posting_list_fraction(0.5)
+                    .terms_per_document(1),
+                &lists
+            ),
+            vec![vec![(0, 5)], vec![(2, 7)], vec![]]
+        );
+    }
+
+    #[test]
+    fn test_static_pruning_args() {
+        let args = StaticPruningArgs::from_iter_safe([
+            "ciff2bmp",
+            "--min-impact",
+            "3",
+            "--posting-list-fraction",
+            "0.5",
+        ])
+        .unwrap();
+        let mut expected = StaticPruning::default();
+        expected.min_impact(3).posting_list_fraction(0.5);
+        assert_eq!(args.pruning(), expected);
+        assert!(!StaticPruningArgs::from_iter_safe(["ciff2bmp"])
+            .unwrap()
+            .pruning()
+            .is_enabled());
+        assert!(
+            StaticPruningArgs::from_iter_safe(["ciff2bmp", "--posting-list-fraction", "1.5"])
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Vectorized kernels for block scoring.
+//!
+//! Each kernel has an AVX2 version, selected at runtime on `x86_64`, a NEON version on `aarch64`,
+//! and a portable scalar fallback.
+
+/// Number of terms examined at once by [`skip_terms`].
+pub const SKIP_CHUNK: usize = 16;
+
+/// Terms skipped by [`skip_terms`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Skip {
+    pub terms: usize,
+    /// Sum of the term id differences of the skipped terms.
+    pub delta: u32,
+    /// Number of bytes used by the postings of the skipped terms.
+    pub postings_len: usize,
+}
+
+/// Counts the leading terms of a chunk of a block whose id is less than `gap` above the current
+/// term id, given the term id differences and postings headers of the next [`SKIP_CHUNK`] terms
+/// (see [`BlockForwardIndex`](super::forward_index::BlockForwardIndex)).
+///
+/// Returns `None` if any difference or header takes more than one byte, in which case the chunk
+/// must be decoded one term at a time.
+#[inline]
+pub fn skip_terms(
+    deltas: &[u8; SKIP_CHUNK],
+    headers: &[u8; SKIP_CHUNK],
+    gap: u32,
+    block_size: usize,
+) -> Option<Skip> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { avx2::skip_terms(deltas, headers, gap, block_size) };
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        return unsafe { neon::skip_terms(deltas, headers, gap, block_size) };
+    }
+    #[allow(unreachable_code)]
+    skip_terms_scalar(deltas, headers, gap, block_size)
+}
+
+/// Portable version of [`skip_terms`].
+pub fn skip_terms_scalar(
+    deltas: &[u8; SKIP_CHUNK],
+    headers: &[u8; SKIP_CHUNK],
+    gap: u32,
+    block_size: usize,
+) -> Option<Skip> {
+    if deltas.iter().chain(headers).any(|&byte| byte >= 0x80) {
+        return None;
+    }
+    let mut skip = Skip::default();
+    for (&delta, &header) in deltas.iter().zip(headers) {
+        if skip.delta + delta as u32 >= gap {
+            break;
+        }
+        skip.terms += 1;
+        skip.delta += delta as u32;
+        skip.postings_len += if header & 1 == 1 {
+            block_size
+        } else {
+            header as usize
+        };
+    }
+    Some(skip)
+}
+
+/// Adds `weight * impacts[i]` to `scores[i]`, for a weight of at most `u8::MAX`.
+#[inline]
+pub fn accumulate_u16(scores: &mut [u16], impacts: &[u8], weight: u16) {
+    debug_assert!(weight <= u8::MAX as u16);
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { avx2::accumulate_u16(scores, impacts, weight) };
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        return unsafe { neon::accumulate_u16(scores, impacts, weight) };
+    }
+    #[allow(unreachable_code)]
+    accumulate_scalar(scores, impacts, weight)
+}
+
+/// Adds `weight * impacts[i]` to `scores[i]`.
+#[inline]
+pub fn accumulate_u32(scores: &mut [u32], impacts: &[u8], weight: u16) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { avx2::accumulate_u32(scores, impacts, weight) };
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        return unsafe { neon::accumulate_u32(scores, impacts, weight) };
+    }
+    #[allow(unreachable_code)]
+    accumulate_scalar(scores, impacts, weight)
+}
+
+/// Adds `weight * values[i]` to `scores[i]`, saturating, for a weight of at most `u8::MAX`.
+#[inline]
+pub fn accumulate_saturating_u16(scores: &mut [u16], values: &[u8], weight: u16) {
+    debug_assert!(weight <= u8::MAX as u16);
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { avx2::accumulate_saturating_u16(scores, values, weight) };
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        return unsafe { neon::accumulate_saturating_u16(scores, values, weight) };
+    }
+    #[allow(unreachable_code)]
+    accumulate_saturating_scalar(scores, values, weight)
+}
+
+/// Adds `weight * values[i]` to `scores[i]`, saturating.
+#[inline]
+pub fn accumulate_saturating_u32(scores: &mut [u32], values: &[u8], weight: u16) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { avx2::accumulate_saturating_u32(scores, values, weight) };
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        return unsafe { neon::accumulate_saturating_u32(scores, values, weight) };
+    }
+    #[allow(unreachable_code)]
+    accumulate_saturating_scalar(scores, values, weight)
+}
+
+/// Portable version of [`accumulate_saturating_u16`] and [`accumulate_saturating_u32`].
+#[inline]
+pub fn accumulate_saturating_scalar<S: crate::query::score::Score>(
+    scores: &mut [S],
+    values: &[u8],
+    weight: u16,
+) {
+    for (score, &value) in scores.iter_mut().zip(values) {
+        *score = score.saturating_add(S::product(weight, value));
+    }
+}
+
+/// Portable version of [`accumulate_u16`] and [`accumulate_u32`].
+#[inline]
+pub fn accumulate_scalar<S: crate::query::score::Score>(
+    scores: &mut [S],
+    impacts: &[u8],
+    weight: u16,
+) {
+    for (score, &impact) in scores.iter_mut().zip(impacts) {
+        *score += S::product(weight, impact);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use super::{Skip, SKIP_CHUNK};
+    use std::arch::x86_64::*;
+
+    /// Inclusive prefix sum of 16 `u16`.
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn prefix_sum_epi16(mut v: __m256i) -> __m256i {
+        v = _mm256_add_epi16(v, _mm256_slli_si256::<2>(v));
+        v = _mm256_add_epi16(v, _mm256_slli_si256::<4>(v));
+        v = _mm256_add_epi16(v, _mm256_slli_si256::<8>(v));
+        // Carries the total of the low lane over to the high lane.
+        let last = _mm256_shuffle_epi8(v, _mm256_set1_epi16(0x0F0E));
+        _mm256_add_epi16(v, _mm256_permute2x128_si256::<0x08>(last, last))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn skip_terms(
+        deltas: &[u8; SKIP_CHUNK],
+        headers: &[u8; SKIP_CHUNK],
+        gap: u32,
+        block_size: usize,
+    ) -> Option<Skip> {
+        let d = _mm_loadu_si128(deltas.as_ptr() as *const _);
+        let h = _mm_loadu_si128(headers.as_ptr() as *const _);
+        if _mm_movemask_epi8(_mm_or_si128(d, h)) != 0 {
+            return None;
+        }
+        // Term ids are increasing, so the terms before `gap` are the leading ones.
+        let prefix = prefix_sum_epi16(_mm256_cvtepu8_epi16(d));
+        let gap = _mm256_set1_epi16(gap.min(i16::MAX as u32) as i16);
+        let before = _mm256_cmpgt_epi16(gap, prefix);
+        let terms = _mm256_movemask_epi8(before).count_ones() as usize / 2;
+        if terms == 0 {
+            return Some(Skip::default());
+        }
+
+        let h = _mm256_cvtepu8_epi16(h);
+        let one = _mm256_set1_epi16(1);
+        let dense = _mm256_cmpeq_epi16(_mm256_and_si256(h, one), one);
+        let lens = _mm256_blendv_epi8(h, _mm256_set1_epi16(block_size as i16), dense);
+        let lens = prefix_sum_epi16(_mm256_and_si256(lens, before));
+
+        let mut prefix_out = [0u16; SKIP_CHUNK];
+        let mut lens_out = [0u16; SKIP_CHUNK];
+        _mm256_storeu_si256(prefix_out.as_mut_ptr() as *mut _, prefix);
+        _mm256_storeu_si256(lens_out.as_mut_ptr() as *mut _, lens);
+        Some(Skip {
+            terms,
+            delta: prefix_out[terms - 1] as u32,
+            postings_len: lens_out[terms - 1] as usize,
+        })
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn accumulate_u16(scores: &mut [u16], impacts: &[u8], weight: u16) {
+        let len = scores.len().min(impacts.len());
+        let w = _mm256_set1_epi16(weight as i16);
+        let mut i = 0;
+        while i + 16 <= len {
+            let imp = _mm256_cvtepu8_epi16(_mm_loadu_si128(impacts.as_ptr().add(i) as *const _));
+            let acc = _mm256_loadu_si256(scores.as_ptr().add(i) as *const _);
+            let acc = _mm256_add_epi16(acc, _mm256_mullo_epi16(imp, w));
+            _mm256_storeu_si256(scores.as_mut_ptr().add(i) as *mut _, acc);
+            i += 16;
+        }
+        super::accumulate_scalar(&mut scores[i..len], &impacts[i..len], weight);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn accumulate_u32(scores: &mut [u32], impacts: &[u8], weight: u16) {
+        let len = scores.len().min(impacts.len());
+        let w = _mm256_set1_epi32(weight as i32);
+        let mut i = 0;
+        while i + 8 <= len {
+            let imp = _mm256_cvtepu8_epi32(_mm_loadl_epi64(impacts.as_ptr().add(i) as *const _));
+            let acc = _mm256_loadu_si256(scores.as_ptr().add(i) as *const _);
+            let acc = _mm256_add_epi32(acc, _mm256_mullo_epi32(imp, w));
+            _mm256_storeu_si256(scores.as_mut_ptr().add(i) as *mut _, acc);
+            i += 8;
+        }
+        super::accumulate_scalar(&mut scores[i..len], &impacts[i..len], weight);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn accumulate_saturating_u16(scores: &mut [u16], values: &[u8], weight: u16) {
+        let len = scores.len().min(values.len());
+        let w = _mm256_set1_epi16(weight as i16);
+        let mut i = 0;
+        while i + 16 <= len {
+            let v = _mm256_cvtepu8_epi16(_mm_loadu_si128(values.as_ptr().add(i) as *const _));
+            let acc = _mm256_loadu_si256(scores.as_ptr().add(i) as *const _);
+            let acc = _mm256_adds_epu16(acc, _mm256_mullo_epi16(v, w));
+            _mm256_storeu_si256(scores.as_mut_ptr().add(i) as *mut _, acc);
+            i += 16;
+        }
+        super::accumulate_saturating_scalar(&mut scores[i..len], &values[i..len], weight);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn accumulate_saturating_u32(scores: &mut [u32], values: &[u8], weight: u16) {
+        let len = scores.len().min(values.len());
+        let w = _mm256_set1_epi32(weight as i32);
+        let all_ones = _mm256_set1_epi32(-1);
+        let mut i = 0;
+        while i + 8 <= len {
+            let v = _mm256_cvtepu8_epi32(_mm_loadl_epi64(values.as_ptr().add(i) as *const _));
+            let acc = _mm256_loadu_si256(scores.as_ptr().add(i) as *const _);
+            let sum = _mm256_add_epi32(acc, _mm256_mullo_epi32(v, w));
+            // The sum wrapped around iff it is smaller than the accumulator.
+            let no_overflow = _mm256_cmpeq_epi32(_mm256_max_epu32(sum, acc), sum);
+            let sum = _mm256_blendv_epi8(all_ones, sum, no_overflow);
+            _mm256_storeu_si256(scores.as_mut_ptr().add(i) as *mut _, sum);
+            i += 8;
+        }
+        super::accumulate_saturating_scalar(&mut scores[i..len], &values[i..len], weight);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use super::{Skip, SKIP_CHUNK};
+    use std::arch::aarch64::*;
+
+    /// Inclusive prefix sum of 8 `u16`.
+    #[inline]
+    unsafe fn prefix_sum_u16(v: uint16x8_t) -> uint16x8_t {
+        let zero = vdupq_n_u16(0);
+        let v = vaddq_u16(v, vextq_u16::<7>(zero, v));
+        let v = vaddq_u16(v, vextq_u16::<6>(zero, v));
+        vaddq_u16(v, vextq_u16::<4>(zero, v))
+    }
+
+    /// Inclusive prefix sum of 16 `u16`, given as two halves.
+    #[inline]
+    unsafe fn prefix_sum_u16x16(lo: uint16x8_t, hi: uint16x8_t) -> (uint16x8_t, uint16x8_t) {
+        let lo = prefix_sum_u16(lo);
+        let hi = vaddq_u16(prefix_sum_u16(hi), vdupq_n_u16(vgetq_lane_u16::<7>(lo)));
+        (lo, hi)
+    }
+
+    /// Postings length of each term, zeroed outside of `mask`.
+    #[inline]
+    unsafe fn postings_lens(headers: uint16x8_t, block_size: u16, mask: uint16x8_t) -> uint16x8_t {
+        let dense = vtstq_u16(headers, vdupq_n_u16(1));
+        vandq_u16(vbslq_u16(dense, vdupq_n_u16(block_size), headers), mask)
+    }
+
+    pub unsafe fn skip_terms(
+        deltas: &[u8; SKIP_CHUNK],
+        headers: &[u8; SKIP_CHUNK],
+        gap: u32,
+        block_size: usize,
+    ) -> Option<Skip> {
+        let d = vld1q_u8(deltas.as_ptr());
+        let h = vld1q_u8(headers.as_ptr());
+        if vmaxvq_u8(vorrq_u8(d, h)) >= 0x80 {
+            return None;
+        }
+        // Term ids are increasing, so the terms before `gap` are the leading ones.
+        let (prefix_lo, prefix_hi) =
+            prefix_sum_u16x16(vmovl_u8(vget_low_u8(d)), vmovl_u8(vget_high_u8(d)));
+        let gap = vdupq_n_u16(gap.min(u16::MAX as u32) as u16);
+        let before_lo = vcltq_u16(prefix_lo, gap);
+        let before_hi = vcltq_u16(prefix_hi, gap);
+        let terms = (vaddvq_u16(vshrq_n_u16::<15>(before_lo))
+            + vaddvq_u16(vshrq_n_u16::<15>(before_hi))) as usize;
+        if terms == 0 {
+            return Some(Skip::default());
+        }
+
+        let block_size = block_size as u16;
+        let (lens_lo, lens_hi) = prefix_sum_u16x16(
+            postings_lens(vmovl_u8(vget_low_u8(h)), block_size, before_lo),
+            postings_lens(vmovl_u8(vget_high_u8(h)), block_size, before_hi),
+        );
+
+        let mut prefix_out = [0u16; SKIP_CHUNK];
+        let mut lens_out = [0u16; SKIP_CHUNK];
+        vst1q_u16(prefix_out.as_mut_ptr(), prefix_lo);
+        vst1q_u16(prefix_out.as_mut_ptr().add(8), prefix_hi);
+        vst1q_u16(lens_out.as_mut_ptr(), lens_lo);
+        vst1q_u16(lens_out.as_mut_ptr().add(8), lens_hi);
+        Some(Skip {
+            terms,
+            delta: prefix_out[terms - 1] as u32,
+            postings_len: lens_out[terms - 1] as usize,
+        })
+    }
+
+    pub unsafe fn accumulate_u16(scores: &mut [u16], impacts: &[u8], weight: u16) {
+        let len = scores.len().min(impacts.len());
+        let w = vdup_n_u8(weight as u8);
+        let mut i = 0;
+        while i + 8 <= len {
+            let imp = vld1_u8(impacts.as_ptr().add(i));
+            let acc = vld1q_u16(scores.as_ptr().add(i));
+            vst1q_u16(scores.as_mut_ptr().add(i), vmlal_u8(acc, imp, w));
+            i += 8;
+        }
+        super::accumulate_scalar(&mut scores[i..len], &impacts[i..len], weight);
+    }
+
+    pub unsafe fn accumulate_u32(scores: &mut [u32], impacts: &[u8], weight: u16) {
+        let len = scores.len().min(impacts.len());
+        let w = vdup_n_u16(weight);
+        let mut i = 0;
+        while i + 8 <= len {
+            let imp = vmovl_u8(vld1_u8(impacts.as_ptr().add(i)));
+            let lo = vld1q_u32(scores.as_ptr().add(i));
+            let hi = vld1q_u32(scores.as_ptr().add(i + 4));
+            vst1q_u32(
+                scores.as_mut_ptr().add(i),
+                vmlal_u16(lo, vget_low_u16(imp), w),
+            );
+            vst1q_u32(
+                scores.as_mut_ptr().add(i + 4),
+                vmlal_u16(hi, vget_high_u16(imp), w),
+            );
+            i += 8;
+        }
+        super::accumulate_scalar(&mut scores[i..len], &impacts[i..len], weight);
+    }
+
+    pub unsafe fn accumulate_saturating_u16(scores: &mut [u16], values: &[u8], weight: u16) {
+        let len = scores.len().min(values.len());
+        let w = vdup_n_u8(weight as u8);
+        let mut i = 0;
+        while i + 8 <= len {
+            let v = vld1_u8(values.as_ptr().add(i));
+            let acc = vld1q_u16(scores.as_ptr().add(i));
+            vst1q_u16(scores.as_mut_ptr().add(i), vqaddq_u16(acc, vmull_u8(v, w)));
+            i += 8;
+        }
+        super::accumulate_saturating_scalar(&mut scores[i..len], &values[i..len], weight);
+    }
+
+    pub unsafe fn accumulate_saturating_u32(scores: &mut [u32], values: &[u8], weight: u16) {
+        let len = scores.len().min(values.len());
+        let w = vdup_n_u16(weight);
+        let mut i = 0;
+        while i + 8 <= len {
+            let v = vmovl_u8(vld1_u8(values.as_ptr().add(i)));
+            let lo = vld1q_u32(scores.as_ptr().add(i));
+            let hi = vld1q_u32(scores.as_ptr().add(i + 4));
+            vst1q_u32(
+                scores.as_mut_ptr().add(i),
+                vqaddq_u32(lo, vmull_u16(vget_low_u16(v), w)),
+            );
+            vst1q_u32(
+                scores.as_mut_ptr().add(i + 4),
+                vqaddq_u32(hi, vmull_u16(vget_high_u16(v), w)),
+            );
+            i += 8;
+        }
+        super::accumulate_saturating_scalar(&mut scores[i..len], &values[i..len], weight);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_skip_terms() {
+        let deltas: [u8; SKIP_CHUNK] = std::array::from_fn(|i| (i * 5 % 17) as u8 + 1);
+        let headers: [u8; SKIP_CHUNK] = std::array::from_fn(|i| (i * 3 % 11) as u8);
+        for gap in 0..200 {
+            let expected = skip_terms_scalar(&deltas, &headers, gap, 128);
+            assert_eq!(skip_terms(&deltas, &headers, gap, 128), expected);
+        }
+        assert_eq!(
+            skip_terms(&deltas, &headers, u32::MAX, 128).unwrap().terms,
+            SKIP_CHUNK
+        );
+        let mut long = deltas;
+        long[3] = 0x80;
+        assert_eq!(skip_terms(&long, &headers, 100, 128), None);
+    }
+
+    #[test]
+    fn test_accumulate() {
+        let impacts: Vec<u8> = (0..37).map(|i| (i * 7) as u8).collect();
+        let mut expected = vec![3u16; 37];
+        accumulate_scalar(&mut expected, &impacts, 255);
+        let mut scores = vec![3u16; 37];
+        accumulate_u16(&mut scores, &impacts, 255);
+        assert_eq!(scores, expected);
+
+        let mut expected = vec![3u32; 37];
+        accumulate_scalar(&mut expected, &impacts, 60000);
+        let mut scores = vec![3u32; 37];
+        accumulate_u32(&mut scores, &impacts, 60000);
+        assert_eq!(scores, expected);
+    }
+
+    #[test]
+    fn test_accumulate_saturating() {
+        let values: Vec<u8> = (0..37).map(|i| (i * 7) as u8).collect();
+        let start: Vec<u16> = (0..37).map(|i| i * 1800).collect();
+        let mut expected = start.clone();
+        accumulate_saturating_scalar(&mut expected, &values, 255);
+        assert!(expected.contains(&u16::MAX));
+        let mut scores = start;
+        accumulate_saturating_u16(&mut scores, &values, 255);
+        assert_eq!(scores, expected);
+
+        let start: Vec<u32> = (0..37).map(|i| u32::MAX - i * 300_000).collect();
+        let mut expected = start.clone();
+        accumulate_saturating_scalar(&mut expected, &values, 60000);
+        assert!(expected.contains(&u32::MAX));
+        let mut scores = start;
+        accumulate_saturating_u32(&mut scores, &values, 60000);
+        assert_eq!(scores, expected);
+    }
+}
@@ -0,0 +1,291 @@
+use super::forward_index::fwd2bfwd_with_progress;
+use super::inverted_index::IndexBuilder;
+use super::metadata::DocumentMetadata;
+use super::pruning::StaticPruning;
+use super::BmpIndex;
+use crate::util::quiet_progress_bar;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/// Builds a [`BmpIndex`] from documents, or from posting lists.
+///
+/// Only the posting lists are kept while adding documents: the forward index is derived from them
+/// by [`IndexWriter::finish`], after the static pruning, so that both always hold the same
+/// postings.
+pub struct IndexWriter {
+    bsize: usize,
+    compress_range: bool,
+    pruning: StaticPruning,
+    quiet: bool,
+    builder: IndexBuilder,
+    term_ids: HashMap<String, u32>,
+    metadata: Option<DocumentMetadata>,
+}
+
+impl IndexWriter {
+    /// Creates a writer grouping documents into blocks of `bsize` documents.
+    pub fn new(bsize: usize) -> Self {
+        IndexWriter {
+            bsize,
+            compress_range: false,
+            pruning: StaticPruning::default(),
+            quiet: false,
+            builder: IndexBuilder::new(0, bsize),
+            term_ids: HashMap::new(),
+            metadata: None,
+        }
+    }
+
+    pub fn compress_range(&mut self, compress_range: bool) -> &mut Self {
+        self.compress_range = compress_range;
+        self
+    }
+
+    /// Sets the static pruning applied to the postings. Defaults to keeping every posting.
+    pub fn static_pruning(&mut self, pruning: StaticPruning) -> &mut Self {
+        self.pruning = pruning;
+        self
+    }
+
+    /// Hides the progress bars. Messages are logged with the `log` crate either way.
+    pub fn quiet(&mut self, quiet: bool) -> &mut Self {
+        self.quiet = quiet;
+        self
+    }
+
+    pub fn num_documents(&self) -> usize {
+        self.builder.num_documents()
+    }
+
+    pub fn num_terms(&self) -> usize {
+        self.term_ids.len()
+    }
+
+    /// Id of `term`, which is added with an empty posting list if new.
+    pub fn term_id(&mut self, term: &str) -> u32 {
+        if let Some(&term_id) = self.term_ids.get(term) {
+            return term_id;
+        }
+        let term_id = self.term_ids.len() as u32;
+        self.term_ids.insert(term.to_string(), term_id);
+        self.builder.insert_term(term, Vec::new());
+        term_id
+    }
+
+    /// Adds a document with the impacts of its terms, given by id (see [`IndexWriter::term_id`]),
+    /// and returns its id.
+    pub fn add_document(&mut self, name: &str, vector: &[(u32, u32)]) -> Result<u32> {
+        if let Some(&(term_id, _)) = vector
+            .iter()
+            .find(|&&(term_id, _)| term_id as usize >= self.term_ids.len())
+        {
+            anyhow::bail!("Unknown term id {} in document {}", term_id, name);
+        }
+        let doc_id = self.builder.insert_document(name);
+        for &(term_id, impact) in vector {
+            self.builder.push_posting(term_id, doc_id, impact);
+        }
+        Ok(doc_id)
+    }
+
+    /// Adds a document whose postings are given by [`IndexWriter::add_posting_list`], and returns
+    /// its id.
+    pub fn add_document_name(&mut self, name: &str) -> u32 {
+        self.builder.insert_document(name)
+    }
+
+    /// Sets the metadata of document `doc_id`, a JSON object such as `{"title": ..., "url": ...}`.
+    /// Documents are given metadata by increasing id; those skipped have none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document was not added, if a later document was already given
+    /// metadata, or if `metadata` is not a JSON object.
+    pub fn set_metadata(&mut self, doc_id: u32, metadata: &str) -> Result<()> {
+        if doc_id as usize >= self.num_documents() {
+            anyhow::bail!("Metadata of document {}, which was not added", doc_id);
+        }
+        let document_metadata = self.metadata.get_or_insert_with(DocumentMetadata::default);
+        if (doc_id as usize) < document_metadata.len() {
+            anyhow::bail!(
+                "Metadata of document {}, but document {} already has metadata",
+                doc_id,
+                document_metadata.len() - 1
+            );
+        }
+        while document_metadata.len() < doc_id as usize {
+            document_metadata.push(None)?;
+        }
+        document_metadata.push(Some(metadata))
+    }
+
+    /// Adds a new term with its `(doc_id, impact)` postings, by increasing document id, and returns
+    /// its id.
+    pub fn add_posting_list(&mut self, term: &str, postings: Vec<(u32, u32)>) -> Result<u32> {
+        if self.term_ids.contains_key(term) {
+            anyhow::bail!("Duplicate term {}", term);
+        }
+        let term_id = self.term_ids.len() as u32;
+        self.term_ids.insert(term.to_string(), term_id);
+        self.builder.insert_term(term, postings);
+        Ok(term_id)
+    }
+
+    /// Checks that the index can be built, i.e. that [`IndexWriter::finish`] will not fail, so that
+    /// the writer can be kept otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a posting list refers to a document that was not added.
+    pub fn check(&self) -> Result<()> {
+        let num_documents = self.num_documents();
+        if let Some(&(doc_id, _)) = self
+            .builder
+            .posting_lists()
+            .iter()
+            .flatten()
+            .find(|&&(doc_id, _)| doc_id as usize >= num_documents)
+        {
+            return Err(anyhow!(
+                "Posting of document {}, but only {} documents were added",
+                doc_id,
+                num_documents
+            ));
+        }
+        Ok(())
+    }
+
+    /// Applies the static pruning and builds the index.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`IndexWriter::check`].
+    pub fn finish(self) -> Result<BmpIndex> {
+        self.check()?;
+        let mut builder = self.builder;
+        let num_documents = builder.num_documents();
+
+        if self.pruning.is_enabled() {
+            log::info!("Pruning postings");
+            let filter = self.pruning.filter(builder.posting_lists(), num_documents);
+            let total: usize = builder.posting_lists().iter().map(Vec::len).sum();
+            let kept = builder.retain_postings(&filter);
+            log::info!("postings kept: {} / {}", kept, total);
+        }
+
+        log::info!("Building forward index");
+        let (inverted_index, forward_index) = builder.build_with_forward_index(self.compress_range);
+        log::info!("Converting to blocked forward index");
+        let progress = quiet_progress_bar("Blocking documents", 0, self.quiet);
+        let b_forward_index = fwd2bfwd_with_progress(&forward_index, self.bsize, &progress);
+
+        let num_blocks = b_forward_index.num_blocks();
+        log::info!("block numbers: {}", num_blocks);
+        let mut tot = 0;
+        let mut tot_avg_docs = 0.0;
+        for block_id in 0..num_blocks {
+            let (terms, postings) = b_forward_index
+                .block_terms(block_id)
+                .fold((0, 0), |(t, p), (_, docs)| (t + 1, p + docs.len()));
+            tot += terms;
+            tot_avg_docs += postings as f32 / terms.max(1) as f32;
+        }
+        log::info!("avg terms per block: {}", tot / num_blocks.max(1));
+        log::info!(
+            "avg docs per term: {}",
+            tot_avg_docs / num_blocks.max(1) as f32
+        );
+        log::info!(
+            "forward index: {} bytes, {:.2} bytes per posting",
+            b_forward_index.size_in_bytes(),
+            b_forward_index.bytes_per_posting()
+        );
+        log::info!(
+            "document lexicon: {} bytes",
+            inverted_index.documents().size_in_bytes()
+        );
+        let index = BmpIndex::new(inverted_index, b_forward_index);
+        match self.metadata {
+            Some(mut metadata) => {
+                while metadata.len() < num_documents {
+                    metadata.push(None)?;
+                }
+                log::info!("document metadata: {} bytes", metadata.size_in_bytes());
+                index.with_metadata(metadata)
+            }
+            None => Ok(index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_documents_and_posting_lists() {
+        // The same collection, added by document and by posting list.
+        let mut by_document = IndexWriter::new(2);
+        let (a, b) = (by_document.term_id("a"), by_document.term_id("b"));
+        by_document.add_document("d0", &[(a, 3), (b, 1)]).unwrap();
+        by_document.add_document("d1", &[]).unwrap();
+        by_document.add_document("d2", &[(b, 5)]).unwrap();
+        assert!(by_document.add_document("d3", &[(2, 1)]).is_err());
+
+        let mut by_term = IndexWriter::new(2);
+        by_term.add_posting_list("a", vec![(0, 3)]).unwrap();
+        by_term.add_posting_list("b", vec![(0, 1), (2, 5)]).unwrap();
+        assert!(by_term.add_posting_list("b", vec![]).is_err());
+        for name in ["d0", "d1", "d2"] {
+            by_term.add_document_name(name);
+        }
+
+        for writer in [by_document, by_term] {
+            let index = writer.finish().unwrap();
+            let (inverted_index, forward_index) = index.into_parts();
+            assert_eq!(
+                inverted_index.documents().iter().collect::<Vec<_>>(),
+                ["d0", "d1", "d2"]
+            );
+            let b = inverted_index.posting_list("b").unwrap();
+            assert_eq!((b.doc_freq(), b.max_impact()), (2, 5));
+            let block_terms: Vec<_> = (0..forward_index.num_blocks())
+                .map(|block_id| {
+                    forward_index
+                        .block_terms(block_id)
+                        .map(|(term_id, postings)| (term_id, postings.iter().collect::<Vec<_>>()))
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            assert_eq!(
+                block_terms,
+                vec![
+                    vec![(0, vec![(0, 3)]), (1, vec![(0, 1)])],
+                    vec![(1, vec![(0, 5)])]
+                ]
+            );
+        }
+
+        let mut with_metadata = IndexWriter::new(2);
+        for name in ["d0", "d1", "d2"] {
+            with_metadata.add_document(name, &[]).unwrap();
+        }
+        with_metadata.set_metadata(1, r#"{"title": "b"}"#).unwrap();
+        assert!(with_metadata.set_metadata(0, "{}").is_err());
+        assert!(with_metadata.set_metadata(3, "{}").is_err());
+        let index = with_metadata.finish().unwrap();
+        let metadata: Vec<_> = (0..3)
+            .map(|doc_id| index.document_metadata(doc_id))
+            .collect();
+        assert_eq!(metadata, vec![None, Some(r#"{"title": "b"}"#), None]);
+
+        let mut missing_document = IndexWriter::new(2);
+        missing_document
+            .add_posting_list("a", vec![(0, 3), (1, 2)])
+            .unwrap();
+        missing_document.add_document_name("d0");
+        assert!(missing_document.check().is_err());
+        missing_document.add_document_name("d1");
+        assert!(missing_document.check().is_ok());
+    }
+}
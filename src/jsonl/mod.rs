@@ -0,0 +1,355 @@
+use anyhow::{anyhow, Context};
+use flate2::read::MultiGzDecoder;
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::index::pruning::StaticPruning;
+use crate::index::writer::IndexWriter;
+use crate::index::{quantize_impact, BmpIndex};
+
+type Result<T> = anyhow::Result<T>;
+
+/// Number of lines parsed in parallel at a time.
+const CHUNK_LINES: usize = 10_000;
+
+#[derive(Deserialize)]
+struct JsonDocument {
+    id: serde_json::Value,
+    /// Sorted, so that terms are given ids in the same order on every run.
+    vector: BTreeMap<String, f32>,
+    #[serde(flatten)]
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+/// JSONL to BMP converter.
+///
+/// Each line of the input files is a document `{"id": ..., "vector": {term: weight, ...}}`, as in
+/// Anserini's `JsonVectorCollection`. Files ending in `.gz` are decompressed.
+#[derive(Debug, Default, Clone)]
+pub struct JsonlToBmp {
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    bsize: Option<usize>,
+    compress_range: bool,
+    pruning: StaticPruning,
+    scale: Option<f32>,
+    metadata: bool,
+    quiet: bool,
+}
+
+impl JsonlToBmp {
+    /// Adds a JSONL file, read after the ones added before. At least one is required.
+    pub fn input_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.inputs.push(path.into());
+        self
+    }
+
+    /// Sets BMP (uncompressed) inverted index path. Required by [`JsonlToBmp::to_bmp`].
+    pub fn output_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.output = Some(path.into());
+        self
+    }
+
+    pub fn bsize(&mut self, bsize: usize) -> &mut Self {
+        self.bsize = Some(bsize);
+        self
+    }
+
+    pub fn compress_range(&mut self, compress_range: bool) -> &mut Self {
+        self.compress_range = compress_range;
+        self
+    }
+
+    /// Sets the static pruning applied to the postings. Defaults to keeping every posting.
+    pub fn static_pruning(&mut self, pruning: StaticPruning) -> &mut Self {
+        self.pruning = pruning;
+        self
+    }
+
+    /// Sets the factor by which weights are multiplied before being truncated into impacts of at
+    /// most 255. Defaults to mapping the largest weight of the collection to 255, for which all
+    /// the documents are kept in memory until they are read.
+    pub fn scale(&mut self, scale: f32) -> &mut Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// Stores the fields of the documents other than `id` and `vector`, e.g. their title or URL,
+    /// as their metadata.
+    pub fn metadata(&mut self, metadata: bool) -> &mut Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Hides the progress bars. Messages are logged with the `log` crate either way.
+    pub fn quiet(&mut self, quiet: bool) -> &mut Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Builds a BMP index and writes it to the output path.
+    ///
+    /// # Errors
+    ///
+    /// Error will be returned if:
+    ///  - some required parameters are not defined or invalid,
+    ///  - any I/O error occurs during reading input files or writing to the output file,
+    ///  - any input line is not a valid document.
+    pub fn to_bmp(&self) -> Result<()> {
+        let output = self
+            .output
+            .as_ref()
+            .ok_or_else(|| anyhow!("output path undefined"))?;
+        self.build()?.write_to(output)
+    }
+
+    /// Builds a BMP index in memory.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`JsonlToBmp::to_bmp`], except for the output.
+    pub fn build(&self) -> Result<BmpIndex> {
+        if self.inputs.is_empty() {
+            anyhow::bail!("input path undefined");
+        }
+        let bsize = self.bsize.ok_or_else(|| anyhow!("bsize undefined"))?;
+        if bsize == 0 {
+            anyhow::bail!("bsize must be positive");
+        }
+        if let Some(scale) = self.scale {
+            if !(scale.is_finite() && scale > 0.0) {
+                anyhow::bail!("scale must be positive: {}", scale);
+            }
+        }
+        let mut writer = IndexWriter::new(bsize);
+        writer
+            .compress_range(self.compress_range)
+            .static_pruning(self.pruning)
+            .quiet(self.quiet);
+        let mut collection = Collection::new(writer, self.scale, self.metadata);
+        for input in &self.inputs {
+            log::info!("Reading {}", input.display());
+            let file =
+                File::open(input).with_context(|| format!("Unable to open {}", input.display()))?;
+            if input.extension().is_some_and(|ext| ext == "gz") {
+                collection.read(BufReader::new(MultiGzDecoder::new(file)), input)?;
+            } else {
+                collection.read(BufReader::new(file), input)?;
+            }
+        }
+        log::info!(
+            "documents: {}, terms: {}",
+            collection.num_documents(),
+            collection.writer.num_terms()
+        );
+        collection.into_index()
+    }
+}
+
+/// Document read from a JSONL file, with the ids of its terms and their float weights, and its
+/// metadata as a JSON object.
+type ParsedDocument = (String, Vec<(u32, f32)>, Option<String>);
+
+/// Documents read so far. They are quantized and added to the writer as soon as they are read when
+/// the scale is known, and buffered until the scale is derived from their largest weight otherwise.
+struct Collection {
+    writer: IndexWriter,
+    scale: Option<f32>,
+    store_metadata: bool,
+    buffered: Vec<ParsedDocument>,
+}
+
+impl Collection {
+    fn new(writer: IndexWriter, scale: Option<f32>, store_metadata: bool) -> Self {
+        if let Some(scale) = scale {
+            log::info!("Quantizing with scale {}", scale);
+        }
+        Collection {
+            writer,
+            scale,
+            store_metadata,
+            buffered: Vec::new(),
+        }
+    }
+
+    fn num_documents(&self) -> usize {
+        self.writer.num_documents() + self.buffered.len()
+    }
+
+    /// Reads the documents of a JSONL file, parsing chunks of lines in parallel.
+    fn read<R: BufRead>(&mut self, reader: R, source: &Path) -> Result<()> {
+        let mut lines = reader.lines().enumerate();
+        loop {
+            let chunk = lines
+                .by_ref()
+                .take(CHUNK_LINES)
+                .map(|(line_number, line)| {
+                    line.map(|line| (line_number + 1, line)).with_context(|| {
+                        format!(
+                            "Unable to read {} at line {}",
+                            source.display(),
+                            line_number + 1
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if chunk.is_empty() {
+                return Ok(());
+            }
+            let documents = chunk
+                .par_iter()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(line_number, line)| {
+                    let document =
+                        serde_json::from_str::<JsonDocument>(line).with_context(|| {
+                            format!(
+                                "Invalid JSON document in {} at line {}",
+                                source.display(),
+                                line_number
+                            )
+                        })?;
+                    // Such as 1e39, out of the range of `f32`.
+                    if let Some((term, weight)) = document
+                        .vector
+                        .iter()
+                        .find(|(_, weight)| !weight.is_finite())
+                    {
+                        anyhow::bail!(
+                            "Invalid weight {} of term {} in {} at line {}",
+                            weight,
+                            term,
+                            source.display(),
+                            line_number
+                        );
+                    }
+                    Ok(document)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            for document in documents {
+                self.insert(document)?;
+            }
+        }
+    }
+
+    fn insert(&mut self, document: JsonDocument) -> Result<()> {
+        let name = match document.id {
+            serde_json::Value::String(id) => id,
+            id => id.to_string(),
+        };
+        // Terms are given ids by order of appearance, even if their impacts are truncated to zero.
+        let vector = document
+            .vector
+            .into_iter()
+            .filter(|&(_, weight)| weight > 0.0)
+            .map(|(term, weight)| (self.writer.term_id(&term), weight))
+            .collect();
+        let fields = document.fields;
+        let metadata = (self.store_metadata && !fields.is_empty())
+            .then(|| serde_json::Value::Object(fields).to_string());
+        match self.scale {
+            Some(scale) => add_document(&mut self.writer, (name, vector, metadata), scale),
+            None => {
+                self.buffered.push((name, vector, metadata));
+                Ok(())
+            }
+        }
+    }
+
+    /// Adds the buffered documents, quantized so that the largest weight becomes 255, and builds
+    /// the index.
+    fn into_index(mut self) -> Result<BmpIndex> {
+        if self.scale.is_none() {
+            let max = self
+                .buffered
+                .iter()
+                .flat_map(|(_, vector, _)| vector)
+                .map(|&(_, weight)| weight)
+                .fold(0.0, f32::max);
+            let scale = if max > 0.0 { u8::MAX as f32 / max } else { 1.0 };
+            log::info!("Quantizing with scale {}", scale);
+            for document in std::mem::take(&mut self.buffered) {
+                add_document(&mut self.writer, document, scale)?;
+            }
+        }
+        self.writer.finish()
+    }
+}
+
+/// Adds a document, its weights quantized with `scale`, and its metadata.
+fn add_document(
+    writer: &mut IndexWriter,
+    (name, vector, metadata): ParsedDocument,
+    scale: f32,
+) -> Result<()> {
+    let vector: Vec<(u32, u32)> = vector
+        .into_iter()
+        .map(|(term_id, weight)| (term_id, quantize_impact(weight, scale)))
+        .filter(|&(_, impact)| impact > 0)
+        .collect();
+    let doc_id = writer.add_document(&name, &vector)?;
+    if let Some(metadata) = metadata {
+        writer.set_metadata(doc_id, &metadata)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_collection() {
+        let jsonl = r#"{"id": "d0", "vector": {"b": 2.0, "a": 0.5}, "title": "A"}
+
+{"id": 7, "vector": {"b": 1.0, "c": 0.001, "d": -1.0}}
+{"id": "d2", "vector": {}}
+"#;
+        // Buffered until the largest weight is known, and streamed to the writer with a scale.
+        for scale in [None, Some(127.5)] {
+            let mut collection = Collection::new(IndexWriter::new(2), scale, true);
+            collection
+                .read(Cursor::new(jsonl), Path::new("test.jsonl"))
+                .unwrap();
+            assert_eq!(collection.num_documents(), 3);
+
+            let index = collection.into_index().unwrap();
+            assert_eq!(index.document_metadata(0), Some(r#"{"title":"A"}"#));
+            assert_eq!(index.document_metadata(1), None);
+            let (index, forward_index) = index.into_parts();
+            assert_eq!(
+                index.documents().iter().collect::<Vec<_>>(),
+                ["d0", "7", "d2"]
+            );
+            // By order of appearance, then lexicographically within a document.
+            assert_eq!(index.terms(), ["a", "b", "c"]);
+            assert_eq!(forward_index.num_blocks(), 2);
+            let b = index.posting_list("b").unwrap();
+            assert_eq!((b.doc_freq(), b.max_impact()), (2, 255));
+            assert_eq!(index.posting_list("a").unwrap().max_impact(), 63);
+            // Truncated to zero.
+            assert_eq!(index.posting_list("c").unwrap().doc_freq(), 0);
+            assert!(index.posting_list("d").is_none());
+        }
+
+        let collection = || Collection::new(IndexWriter::new(2), None, false);
+        let invalid = "{\"id\": \"d0\", \"vector\": {\"a\": 0.5}}\n{\"id\": \"d1\"}\n";
+        let error = collection()
+            .read(Cursor::new(invalid), Path::new("test.jsonl"))
+            .unwrap_err();
+        assert!(format!("{}", error).contains("test.jsonl at line 2"));
+
+        let infinite = "{\"id\": \"d0\", \"vector\": {\"a\": 0.5, \"b\": 1e39}}\n";
+        let error = collection()
+            .read(Cursor::new(infinite), Path::new("test.jsonl"))
+            .unwrap_err();
+        assert_eq!(
+            format!("{}", error),
+            "Invalid weight inf of term b in test.jsonl at line 1"
+        );
+    }
+}
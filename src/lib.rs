@@ -3,9 +3,12 @@
 
 pub mod ciff;
 pub mod index;
+pub mod jsonl;
 mod proto;
 pub mod query;
 pub mod search;
+pub mod server;
 pub mod util;
 
 pub use ciff::CiffToBmp;
+pub use jsonl::JsonlToBmp;
@@ -0,0 +1,15 @@
+#![allow(unknown_lints)]
+#![allow(clippy::all)]
+#![allow(clippy::pedantic)]
+#![allow(dead_code)]
+#![allow(missing_docs)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+#![allow(non_upper_case_globals)]
+#![allow(trivial_casts)]
+#![allow(unsafe_code)]
+#![allow(unused_imports)]
+#![allow(unused_results)]
+#![allow(unused_parens)]
+#![allow(mismatched_lifetime_syntaxes)]
+include!(concat!(env!("OUT_DIR"), "/bmp_search_v1.rs"));
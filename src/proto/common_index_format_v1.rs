@@ -10,4 +10,6 @@
 #![allow(unsafe_code)]
 #![allow(unused_imports)]
 #![allow(unused_results)]
+#![allow(unused_parens)]
+#![allow(mismatched_lifetime_syntaxes)]
 include!(concat!(env!("OUT_DIR"), "/common_index_format_v1.rs"));
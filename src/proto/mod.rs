@@ -1,4 +1,6 @@
+mod bmp_search_v1;
 mod common_index_format_v1;
+pub use bmp_search_v1::{ScoredDocument, SearchRequest, SearchResponse};
 pub use common_index_format_v1::{DocRecord, Header, Posting, PostingsList};
 
 use std::fmt;
@@ -0,0 +1,95 @@
+use crate::query::score::Score;
+use std::cmp::Reverse;
+
+/// Size of the first batch of blocks sorted by [`CandidateBlocks`].
+const FIRST_BATCH_SIZE: usize = 32;
+
+/// Blocks whose upper bound exceeds a threshold, iterated in decreasing order of upper bound, and
+/// of block id for equal upper bounds.
+///
+/// Since the search usually stops after a few blocks, the candidates are not sorted at once:
+/// the largest ones are selected in batches that double in size, and only the batches are sorted.
+/// The cost thus depends on the number of candidates visited rather than on the width of the
+/// scores or the size of the collection.
+pub struct CandidateBlocks<S> {
+    /// Candidates not yet selected, in any order.
+    remaining: Vec<(S, u32)>,
+    /// Selected candidates, in increasing order, so that the next one is the last.
+    batch: Vec<(S, u32)>,
+    batch_size: usize,
+}
+
+impl<S: Score> CandidateBlocks<S> {
+    pub fn new(upper_bounds: &[S], threshold: S) -> Self {
+        let remaining = upper_bounds
+            .iter()
+            .enumerate()
+            .filter(|&(_, &ub)| ub > threshold)
+            .map(|(block_id, &ub)| (ub, block_id as u32))
+            .collect();
+        CandidateBlocks {
+            remaining,
+            batch: Vec::new(),
+            batch_size: FIRST_BATCH_SIZE,
+        }
+    }
+
+    /// Number of candidates not yet returned.
+    pub fn len(&self) -> usize {
+        self.remaining.len() + self.batch.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn next_batch(&mut self) {
+        let key = |&(ub, block_id): &(S, u32)| (ub, Reverse(block_id));
+        let len = self.remaining.len();
+        let start = len.saturating_sub(self.batch_size);
+        if start > 0 {
+            self.remaining.select_nth_unstable_by_key(start, key);
+        }
+        self.batch.extend(self.remaining.drain(start..));
+        self.batch.sort_unstable_by_key(key);
+        self.batch_size *= 2;
+    }
+}
+
+impl<S: Score> Iterator for CandidateBlocks<S> {
+    type Item = (S, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch.is_empty() {
+            self.next_batch();
+        }
+        self.batch.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_candidate_blocks() {
+        let upper_bounds: Vec<u32> = (0..1000u32).map(|b| b * 7919 % 301).collect();
+        let threshold = 10;
+        let mut expected: Vec<_> = upper_bounds
+            .iter()
+            .enumerate()
+            .filter(|&(_, &ub)| ub > threshold)
+            .map(|(b, &ub)| (ub, b as u32))
+            .collect();
+        expected.sort_by_key(|&(ub, b)| (Reverse(ub), b));
+
+        let candidates = CandidateBlocks::new(&upper_bounds, threshold);
+        assert_eq!(candidates.len(), expected.len());
+        assert_eq!(candidates.collect::<Vec<_>>(), expected);
+        assert!(CandidateBlocks::new(&upper_bounds, 1000).next().is_none());
+    }
+}
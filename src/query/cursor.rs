@@ -10,10 +10,12 @@ pub trait Cursor {
 }
 
 pub enum RangeMaxScore<'a> {
-    Compressed(&'a [crate::index::posting_list::CompressedBlock]),
+    Compressed(&'a crate::index::posting_list::CompressedRangeMaxes),
     Raw(&'a [u8]),
 }
 
 pub trait RangeMaxScoreCursor: Cursor {
-    fn range_max_scores(&self) -> RangeMaxScore;
+    fn range_max_scores(&self) -> RangeMaxScore<'_>;
+
+    fn superblock_maxes(&self) -> &crate::index::posting_list::SuperblockMaxes;
 }
@@ -0,0 +1,486 @@
+use crate::index::inverted_index::Index;
+use crate::index::posting_list::PostingList;
+use crate::query::WeightedQuery;
+use anyhow::{anyhow, Context, Result};
+use fst::automaton::{Automaton, Str};
+use regex_automata::dfa::{dense, Automaton as _};
+use regex_automata::util::primitives::StateID;
+use regex_automata::util::start;
+use regex_automata::{Anchored, MatchKind};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::Bound;
+
+/// Default number of index terms a pattern is expanded into.
+pub const DEFAULT_MAX_EXPANSIONS: usize = 50;
+
+/// Largest edit distance of fuzzy patterns.
+pub const MAX_EDIT_DISTANCE: u32 = 3;
+
+/// Size limit of the automata compiled from regular expressions.
+const REGEX_SIZE_LIMIT: usize = 10 << 20;
+
+/// Query term matched against the terms of the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TermPattern {
+    /// The term itself.
+    Exact(String),
+    /// `foo*`: the terms starting with `foo`.
+    Prefix(String),
+    /// `/regex/`: the terms matching the whole regular expression.
+    Regex(String),
+    /// `foo~` or `foo~<distance>`: the terms within the given Levenshtein distance (1 by default)
+    /// of `foo`, counted in characters.
+    Fuzzy { term: String, distance: u32 },
+    /// `[foo TO bar]`: the terms from `foo` to `bar` in lexicographic order. Square brackets
+    /// include the bound and curly ones exclude it, as in `{foo TO bar]`, while `*` leaves a side
+    /// open.
+    Range {
+        lower: Bound<String>,
+        upper: Bound<String>,
+    },
+}
+
+impl TermPattern {
+    /// Parses the syntax of the variants. Terms without it, or with nothing besides it (such as
+    /// `*`), are exact.
+    pub fn parse(term: &str) -> Self {
+        if let Some(prefix) = term.strip_suffix('*').filter(|prefix| !prefix.is_empty()) {
+            return TermPattern::Prefix(prefix.to_string());
+        }
+        if let Some(regex) = term
+            .strip_prefix('/')
+            .and_then(|term| term.strip_suffix('/'))
+            .filter(|regex| !regex.is_empty())
+        {
+            return TermPattern::Regex(regex.to_string());
+        }
+        if let Some(range) = Self::parse_range(term) {
+            return range;
+        }
+        if let Some((fuzzy, distance)) =
+            term.rsplit_once('~').filter(|(fuzzy, _)| !fuzzy.is_empty())
+        {
+            let distance = match distance {
+                "" => Some(1),
+                distance => distance.parse().ok(),
+            };
+            if let Some(distance) = distance.filter(|&distance| distance <= MAX_EDIT_DISTANCE) {
+                return TermPattern::Fuzzy {
+                    term: fuzzy.to_string(),
+                    distance,
+                };
+            }
+        }
+        TermPattern::Exact(term.to_string())
+    }
+
+    /// Parses `[lower TO upper]`, each bracket being square or curly.
+    fn parse_range(term: &str) -> Option<Self> {
+        let (lower_inclusive, term) = match term.strip_prefix('[') {
+            Some(term) => (true, term),
+            None => (false, term.strip_prefix('{')?),
+        };
+        let (upper_inclusive, term) = match term.strip_suffix(']') {
+            Some(term) => (true, term),
+            None => (false, term.strip_suffix('}')?),
+        };
+        let (lower, upper) = term.split_once(" TO ")?;
+        let bound = |term: &str, inclusive: bool| match term {
+            "" => None,
+            "*" => Some(Bound::Unbounded),
+            term if inclusive => Some(Bound::Included(term.to_string())),
+            term => Some(Bound::Excluded(term.to_string())),
+        };
+        Some(TermPattern::Range {
+            lower: bound(lower, lower_inclusive)?,
+            upper: bound(upper, upper_inclusive)?,
+        })
+    }
+}
+
+/// Expansion of query term patterns into the matching terms of the index.
+#[derive(Debug, Clone, Copy)]
+pub struct TermExpansion {
+    max_expansions: usize,
+    max_patterns: Option<usize>,
+    max_regex_length: Option<usize>,
+}
+
+impl Default for TermExpansion {
+    fn default() -> Self {
+        TermExpansion::new(DEFAULT_MAX_EXPANSIONS)
+    }
+}
+
+impl TermExpansion {
+    /// Expands each pattern into at most `max_expansions` terms.
+    pub fn new(max_expansions: usize) -> Self {
+        TermExpansion {
+            max_expansions,
+            max_patterns: None,
+            max_regex_length: None,
+        }
+    }
+
+    /// Limits the number of patterns of a query, e.g. for queries from untrusted clients. Exact
+    /// terms are not counted. Unlimited by default.
+    pub fn max_patterns(&mut self, max_patterns: usize) -> &mut Self {
+        self.max_patterns = Some(max_patterns);
+        self
+    }
+
+    /// Limits the length in bytes of regular expressions, which are compiled into automata.
+    /// Unlimited by default, besides the size limit of the automata.
+    pub fn max_regex_length(&mut self, max_regex_length: usize) -> &mut Self {
+        self.max_regex_length = Some(max_regex_length);
+        self
+    }
+
+    /// Terms of the index matching `pattern`. When there are more than the maximum number of
+    /// expansions, those with the largest maximum impact are kept. Terms are sorted by decreasing
+    /// maximum impact, then lexicographically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a regular expression is invalid, too long or too large.
+    pub fn expand(&self, pattern: &TermPattern, index: &Index) -> Result<Vec<String>> {
+        // The terms kept so far, with the worst one on top: the smallest maximum impact, then the
+        // last in lexicographic order.
+        let mut kept: BinaryHeap<(Reverse<u8>, String)> = BinaryHeap::new();
+        let mut num_matches = 0;
+        let mut keep = |term: &str, posting_list: &PostingList| {
+            num_matches += 1;
+            let max_impact = posting_list.max_impact();
+            // Terms come in lexicographic order, so those with the same maximum impact as the
+            // worst kept term are not kept.
+            if kept.len() == self.max_expansions
+                && kept
+                    .peek()
+                    .is_none_or(|&(Reverse(worst), _)| max_impact <= worst)
+            {
+                return;
+            }
+            kept.push((Reverse(max_impact), term.to_string()));
+            if kept.len() > self.max_expansions {
+                kept.pop();
+            }
+        };
+        match pattern {
+            TermPattern::Exact(term) => {
+                if let Some(posting_list) = index.posting_list(term) {
+                    keep(term, posting_list);
+                }
+            }
+            TermPattern::Prefix(prefix) => {
+                index.for_each_term(Str::new(prefix).starts_with(), keep)
+            }
+            TermPattern::Regex(regex) => {
+                if let Some(max_regex_length) = self.max_regex_length {
+                    if regex.len() > max_regex_length {
+                        anyhow::bail!(
+                            "regular expression /{}/ longer than {} bytes",
+                            regex,
+                            max_regex_length
+                        );
+                    }
+                }
+                index.for_each_term(RegexAutomaton::new(regex)?, keep)
+            }
+            TermPattern::Fuzzy { term, distance } => {
+                index.for_each_term(Levenshtein::new(term, *distance), keep)
+            }
+            TermPattern::Range { lower, upper } => index.for_each_term_in_range(
+                lower.as_ref().map(String::as_str),
+                upper.as_ref().map(String::as_str),
+                keep,
+            ),
+        }
+        if num_matches > kept.len() {
+            log::debug!(
+                "{:?} matches {} terms, keeping {}",
+                pattern,
+                num_matches,
+                kept.len()
+            );
+        }
+        Ok(kept
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(_, term)| term)
+            .collect())
+    }
+
+    /// Replaces the terms of `query` by the terms of the index they match as patterns, with the
+    /// same weight. A term matched by several patterns keeps its largest weight.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query has too many patterns, or as [`TermExpansion::expand`].
+    pub fn expand_query(&self, query: &WeightedQuery, index: &Index) -> Result<WeightedQuery> {
+        if let Some(max_patterns) = self.max_patterns {
+            let num_patterns = query
+                .keys()
+                .filter(|term| !matches!(TermPattern::parse(term), TermPattern::Exact(_)))
+                .count();
+            if num_patterns > max_patterns {
+                anyhow::bail!(
+                    "{} term patterns in the query, but at most {} are allowed",
+                    num_patterns,
+                    max_patterns
+                );
+            }
+        }
+        let mut expanded = WeightedQuery::new();
+        for (term, &weight) in query {
+            for term in self.expand(&TermPattern::parse(term), index)? {
+                let term_weight = expanded.entry(term).or_insert(weight);
+                *term_weight = term_weight.max(weight);
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+/// Automaton matching the terms that a regular expression matches entirely.
+struct RegexAutomaton {
+    dfa: dense::DFA<Vec<u32>>,
+    start: StateID,
+}
+
+impl RegexAutomaton {
+    fn new(regex: &str) -> Result<Self> {
+        let dfa = dense::Builder::new()
+            .configure(
+                dense::Config::new()
+                    .match_kind(MatchKind::All)
+                    .dfa_size_limit(Some(REGEX_SIZE_LIMIT))
+                    .determinize_size_limit(Some(REGEX_SIZE_LIMIT)),
+            )
+            .build(&format!("(?:{})$", regex))
+            .with_context(|| format!("invalid regular expression /{}/", regex))?;
+        let start = dfa
+            .start_state(&start::Config::new().anchored(Anchored::Yes))
+            .map_err(|e| anyhow!("invalid regular expression /{}/: {}", regex, e))?;
+        Ok(RegexAutomaton { dfa, start })
+    }
+}
+
+impl Automaton for RegexAutomaton {
+    type State = StateID;
+
+    fn start(&self) -> StateID {
+        self.start
+    }
+
+    fn is_match(&self, state: &StateID) -> bool {
+        self.dfa.is_match_state(self.dfa.next_eoi_state(*state))
+    }
+
+    fn can_match(&self, state: &StateID) -> bool {
+        !self.dfa.is_dead_state(*state)
+    }
+
+    fn accept(&self, state: &StateID, byte: u8) -> StateID {
+        self.dfa.next_state(*state, byte)
+    }
+}
+
+/// Automaton matching the terms within a Levenshtein distance of a term, in characters.
+struct Levenshtein {
+    term: Vec<char>,
+    distance: u32,
+}
+
+/// Distances between the prefixes of the term and the bytes read so far, as a row of the dynamic
+/// programming table, and the bytes of the character being read. `None` once the distance is
+/// exceeded.
+type LevenshteinState = Option<(Vec<u32>, Vec<u8>)>;
+
+impl Levenshtein {
+    fn new(term: &str, distance: u32) -> Self {
+        Levenshtein {
+            term: term.chars().collect(),
+            distance,
+        }
+    }
+
+    fn next_row(&self, row: &[u32], c: char) -> Vec<u32> {
+        let mut next = Vec::with_capacity(row.len());
+        next.push(row[0] + 1);
+        for (i, &term_char) in self.term.iter().enumerate() {
+            let substitution = row[i] + (term_char != c) as u32;
+            next.push(substitution.min(row[i + 1] + 1).min(next[i] + 1));
+        }
+        next
+    }
+}
+
+impl Automaton for Levenshtein {
+    type State = LevenshteinState;
+
+    fn start(&self) -> LevenshteinState {
+        Some(((0..=self.term.len() as u32).collect(), Vec::new()))
+    }
+
+    fn is_match(&self, state: &LevenshteinState) -> bool {
+        state.as_ref().is_some_and(|(row, pending)| {
+            pending.is_empty() && row[self.term.len()] <= self.distance
+        })
+    }
+
+    fn can_match(&self, state: &LevenshteinState) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &LevenshteinState, byte: u8) -> LevenshteinState {
+        let (row, pending) = state.as_ref()?;
+        let mut pending = pending.clone();
+        pending.push(byte);
+        let char_len = match pending[0] {
+            0x00..=0x7f => 1,
+            0xe0..=0xef => 3,
+            0xf0..=0xff => 4,
+            _ => 2,
+        };
+        if pending.len() < char_len {
+            return Some((row.clone(), pending));
+        }
+        let c = std::str::from_utf8(&pending)
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        let row = self.next_row(row, c);
+        (row.iter().min() <= Some(&self.distance)).then(|| (row, Vec::new()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index::inverted_index::IndexBuilder;
+
+    fn index() -> Index {
+        let mut builder = IndexBuilder::new(0, 2);
+        builder.insert_document("d0");
+        builder.insert_document("d1");
+        for (term, impact) in [
+            ("cat", 5),
+            ("cats", 9),
+            ("catalog", 2),
+            ("coat", 4),
+            ("dog", 7),
+            ("café", 3),
+            ("*", 1),
+        ] {
+            builder.insert_term(term, vec![(0, impact)]);
+        }
+        builder.build(false)
+    }
+
+    #[test]
+    fn test_parse_term_pattern() {
+        assert_eq!(
+            TermPattern::parse("cat*"),
+            TermPattern::Prefix("cat".to_string())
+        );
+        assert_eq!(
+            TermPattern::parse("/c.t/"),
+            TermPattern::Regex("c.t".to_string())
+        );
+        assert_eq!(
+            TermPattern::parse("cat~"),
+            TermPattern::Fuzzy {
+                term: "cat".to_string(),
+                distance: 1
+            }
+        );
+        assert_eq!(
+            TermPattern::parse("cat~2"),
+            TermPattern::Fuzzy {
+                term: "cat".to_string(),
+                distance: 2
+            }
+        );
+        assert_eq!(
+            TermPattern::parse("[cat TO dog}"),
+            TermPattern::Range {
+                lower: Bound::Included("cat".to_string()),
+                upper: Bound::Excluded("dog".to_string())
+            }
+        );
+        assert_eq!(
+            TermPattern::parse("{cat TO *]"),
+            TermPattern::Range {
+                lower: Bound::Excluded("cat".to_string()),
+                upper: Bound::Unbounded
+            }
+        );
+        for exact in [
+            "cat",
+            "*",
+            "/",
+            "//",
+            "~",
+            "cat~9",
+            "cat~x",
+            "[cat]",
+            "[ TO dog]",
+            "[cat TO dog",
+        ] {
+            assert_eq!(
+                TermPattern::parse(exact),
+                TermPattern::Exact(exact.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let index = index();
+        let expand = |pattern: &str, max_expansions: usize| {
+            TermExpansion::new(max_expansions)
+                .expand(&TermPattern::parse(pattern), &index)
+                .unwrap()
+        };
+        assert_eq!(expand("cat", 10), ["cat"]);
+        assert_eq!(expand("*", 10), ["*"]);
+        assert!(expand("bird", 10).is_empty());
+        assert_eq!(expand("cat*", 10), ["cats", "cat", "catalog"]);
+        // The largest maximum impacts are kept.
+        assert_eq!(expand("cat*", 2), ["cats", "cat"]);
+        assert_eq!(expand("/c.t/", 10), ["cat"]);
+        assert_eq!(expand("/c[a-z]*t|dog/", 10), ["dog", "cat", "coat"]);
+        assert_eq!(expand("cat~", 10), ["cats", "cat", "coat"]);
+        assert_eq!(expand("caf~", 10), ["cat", "café"]);
+        assert_eq!(expand("cafe~0", 10), Vec::<String>::new());
+        assert_eq!(
+            expand("[cat TO coat]", 10),
+            ["cats", "cat", "coat", "catalog"]
+        );
+        assert_eq!(expand("{cat TO coat}", 10), ["cats", "catalog"]);
+        assert_eq!(expand("[d TO *]", 10), ["dog"]);
+        assert_eq!(expand("[* TO cat}", 10), ["café", "*"]);
+        assert_eq!(expand("[cat TO cat]", 10), ["cat"]);
+        assert!(expand("[dog TO cat]", 10).is_empty());
+        assert!(expand("cat*", 0).is_empty());
+        assert!(TermExpansion::default()
+            .expand(&TermPattern::parse("/c(/"), &index)
+            .is_err());
+        let mut limited = TermExpansion::new(10);
+        limited.max_patterns(1).max_regex_length(3);
+        assert!(limited.expand(&TermPattern::parse("/c.t/"), &index).is_ok());
+        assert!(limited
+            .expand(&TermPattern::parse("/c.*t/"), &index)
+            .is_err());
+
+        let query = WeightedQuery::from([("cat*".to_string(), 1.0), ("cats".to_string(), 2.0)]);
+        let expanded = TermExpansion::new(2).expand_query(&query, &index).unwrap();
+        assert_eq!(
+            expanded,
+            WeightedQuery::from([("cats".to_string(), 2.0), ("cat".to_string(), 1.0)])
+        );
+        let query = WeightedQuery::from([("cat*".to_string(), 1.0), ("dog~".to_string(), 1.0)]);
+        assert!(limited.expand_query(&query, &index).is_err());
+    }
+}
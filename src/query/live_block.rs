@@ -1,40 +1,205 @@
+use crate::index::posting_list::{CompressedRangeMaxes, SuperblockMaxes, SUPERBLOCK_SIZE};
+use crate::query::score::Score;
+use std::ops::Range;
+
+/// Number of upper bounds updated by all the query terms before moving on, so that they stay in
+/// the L1 cache.
+const TILE_SIZE: usize = 4096;
+
+/// Upper bounds of the superblocks of [`SUPERBLOCK_SIZE`] blocks, from their maxes for each term.
+pub fn compute_superblock_upper_bounds<S: Score>(
+    query_maxes: &[&SuperblockMaxes],
+    query_weights: &[u16],
+    num_superblocks: usize,
+) -> Vec<S> {
+    let mut upper_bounds: Vec<S> = vec![S::default(); num_superblocks];
+    for (&maxes, &weight) in query_maxes.iter().zip(query_weights.iter()) {
+        for (id, max) in maxes.iter() {
+            upper_bounds[id] = upper_bounds[id].saturating_add(S::product(weight, max));
+        }
+    }
+    upper_bounds
+}
+
+/// Ranges of blocks of the live superblocks, merged when contiguous and split into tiles.
+fn live_tiles(live_superblocks: &[bool], vector_len: usize) -> Vec<Range<usize>> {
+    let mut tiles: Vec<Range<usize>> = Vec::new();
+    for (id, _) in live_superblocks
+        .iter()
+        .enumerate()
+        .filter(|(_, &live)| live)
+    {
+        let start = id * SUPERBLOCK_SIZE;
+        let end = vector_len.min(start + SUPERBLOCK_SIZE);
+        match tiles.last_mut() {
+            Some(tile) if tile.end == start && tile.len() < TILE_SIZE => tile.end = end,
+            _ => tiles.push(start..end),
+        }
+    }
+    tiles
+}
+
+/// Upper bounds of the blocks of the live superblocks; the others are left to zero.
 #[inline]
-pub fn compute_upper_bounds_raw(
+pub fn compute_upper_bounds_raw<S: Score>(
     query_ranges: &[&[u8]],
-    query_weights: &[u8],
+    query_weights: &[u16],
     vector_len: usize,
-) -> Vec<u16> {
-    // let vector_len: usize = query_ranges[0].len();
-    let mut upper_bounds: Vec<u16> = vec![0; vector_len];
-
-    // Iterate over each vector in scores and add its elements to the result
-    for (&vec, &weight) in query_ranges.iter().zip(query_weights.iter()) {
-        for (i, &score) in vec.iter().enumerate() {
-            let multiplied = score as u16 * weight as u16;
-            upper_bounds[i] = upper_bounds[i].saturating_add(multiplied);
+    live_superblocks: &[bool],
+) -> Vec<S> {
+    let mut upper_bounds: Vec<S> = vec![S::default(); vector_len];
+
+    for tile in live_tiles(live_superblocks, vector_len) {
+        let start = tile.start;
+        let bounds = &mut upper_bounds[tile];
+        for (&ranges, &weight) in query_ranges.iter().zip(query_weights.iter()) {
+            let end = ranges.len().min(start + bounds.len());
+            if start < end {
+                S::accumulate_saturating(bounds, &ranges[start..end], weight);
+            }
         }
     }
     upper_bounds
 }
 
+/// Upper bounds of the blocks of the live superblocks; the others are left to zero.
 #[inline]
-pub fn compute_upper_bounds(
-    query_ranges: &[&[crate::index::posting_list::CompressedBlock]],
-    query_weights: &[u8],
+pub fn compute_upper_bounds<S: Score>(
+    query_ranges: &[&CompressedRangeMaxes],
+    query_weights: &[u16],
     vector_len: usize,
-) -> Vec<u16> {
-    // let vector_len: usize = query_ranges[0].len();
-    let mut upper_bounds: Vec<u16> = vec![0; vector_len];
-
-    // Iterate over each vector in scores and add its elements to the result
-    for (&vec, &weight) in query_ranges.iter().zip(query_weights.iter()) {
-        for (bid, block) in vec.iter().enumerate() {
-            for &(offset, score) in &block.max_scores {
-                let multiplied = score as u16 * weight as u16;
-                upper_bounds[bid * 256 + offset] =
-                    upper_bounds[bid * 256 + offset].saturating_add(multiplied);
+    live_superblocks: &[bool],
+) -> Vec<S> {
+    let mut upper_bounds: Vec<S> = vec![S::default(); vector_len];
+
+    // Superblocks where a term does not appear are not stored, and thus skipped, as well as the
+    // stored superblocks between live tiles.
+    let mut positions = vec![0; query_ranges.len()];
+    for tile in live_tiles(live_superblocks, vector_len) {
+        let tile_start = tile.start / SUPERBLOCK_SIZE;
+        let tile_end = tile.end.div_ceil(SUPERBLOCK_SIZE);
+        for ((&ranges, pos), &weight) in query_ranges
+            .iter()
+            .zip(positions.iter_mut())
+            .zip(query_weights.iter())
+        {
+            *pos = ranges.seek(*pos, tile_start);
+            while let Some(superblock) = ranges.get(*pos).filter(|sb| sb.id < tile_end) {
+                *pos += 1;
+                let start = superblock.id * SUPERBLOCK_SIZE;
+                let bounds = &mut upper_bounds[start..vector_len.min(start + SUPERBLOCK_SIZE)];
+                if superblock.values.len() == bounds.len() {
+                    S::accumulate_saturating(bounds, superblock.values, weight);
+                    continue;
+                }
+                let mut values = superblock.values.iter();
+                for (word_id, &word) in superblock.bitmap.iter().enumerate() {
+                    let mut word = word;
+                    while word != 0 {
+                        let offset = word_id * 64 + word.trailing_zeros() as usize;
+                        let value = *values.next().unwrap();
+                        bounds[offset] = bounds[offset].saturating_add(S::product(weight, value));
+                        word &= word - 1;
+                    }
+                }
             }
         }
     }
     upper_bounds
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compressed_upper_bounds() {
+        // Sparse, empty, dense and partial superblocks.
+        let num_blocks = 3 * SUPERBLOCK_SIZE + 100;
+        let ranges: Vec<Vec<u8>> = (0..3u32)
+            .map(|t| {
+                (0..num_blocks as u32)
+                    .map(|b| match b as usize / SUPERBLOCK_SIZE {
+                        0 if b % (t + 7) == 0 => (b % 200) as u8 + 1,
+                        2 | 3 if (b + t) % 3 != 0 => (b * (t + 1) % 256) as u8,
+                        _ => 0,
+                    })
+                    .collect()
+            })
+            .collect();
+        let raw: Vec<&[u8]> = ranges.iter().map(Vec::as_slice).collect();
+        let compressed: Vec<_> = ranges
+            .iter()
+            .map(|r| CompressedRangeMaxes::new(r))
+            .collect();
+        let compressed: Vec<_> = compressed.iter().collect();
+        let weights = [200, 1, 255];
+        let live = vec![true; num_blocks.div_ceil(SUPERBLOCK_SIZE)];
+
+        let expected = compute_upper_bounds_raw::<u16>(&raw, &weights, num_blocks, &live);
+        assert!(expected.contains(&u16::MAX));
+        assert_eq!(
+            compute_upper_bounds::<u16>(&compressed, &weights, num_blocks, &live),
+            expected
+        );
+        let weights = [60000, 1, 300];
+        assert_eq!(
+            compute_upper_bounds::<u32>(&compressed, &weights, num_blocks, &live),
+            compute_upper_bounds_raw::<u32>(&raw, &weights, num_blocks, &live)
+        );
+    }
+
+    #[test]
+    fn test_superblock_upper_bounds() {
+        let num_blocks = 4 * SUPERBLOCK_SIZE + 10;
+        let ranges: Vec<Vec<u8>> = (0..2u32)
+            .map(|t| {
+                (0..num_blocks as u32)
+                    .map(|b| match b as usize / SUPERBLOCK_SIZE {
+                        1 => 0,
+                        _ => ((b * 7 + t * 13) % 50) as u8,
+                    })
+                    .collect()
+            })
+            .collect();
+        let raw: Vec<&[u8]> = ranges.iter().map(Vec::as_slice).collect();
+        let compressed: Vec<_> = ranges
+            .iter()
+            .map(|r| CompressedRangeMaxes::new(r))
+            .collect();
+        let compressed: Vec<_> = compressed.iter().collect();
+        let maxes: Vec<_> = ranges.iter().map(|r| SuperblockMaxes::new(r)).collect();
+        let maxes: Vec<_> = maxes.iter().collect();
+        let weights = [3, 5];
+
+        let num_superblocks = num_blocks.div_ceil(SUPERBLOCK_SIZE);
+        let superblock_bounds =
+            compute_superblock_upper_bounds::<u16>(&maxes, &weights, num_superblocks);
+        let all = compute_upper_bounds_raw::<u16>(
+            &raw,
+            &weights,
+            num_blocks,
+            &vec![true; num_superblocks],
+        );
+        for (id, &bound) in superblock_bounds.iter().enumerate() {
+            let blocks = &all[id * SUPERBLOCK_SIZE..num_blocks.min((id + 1) * SUPERBLOCK_SIZE)];
+            assert!(blocks.iter().all(|&ub| ub <= bound));
+        }
+        assert_eq!(superblock_bounds[1], 0);
+
+        let live = [true, false, false, true, true];
+        let expected: Vec<u16> = all
+            .iter()
+            .enumerate()
+            .map(|(b, &ub)| if live[b / SUPERBLOCK_SIZE] { ub } else { 0 })
+            .collect();
+        assert_eq!(
+            compute_upper_bounds_raw::<u16>(&raw, &weights, num_blocks, &live),
+            expected
+        );
+        assert_eq!(
+            compute_upper_bounds::<u16>(&compressed, &weights, num_blocks, &live),
+            expected
+        );
+    }
+}
@@ -1,62 +1,230 @@
+pub mod candidates;
 pub mod cursor;
+pub mod expansion;
 pub mod live_block;
+pub mod pruning;
+pub mod quantizer;
+pub mod score;
 pub mod topk_heap;
 
 use crate::index::inverted_index::Index;
 use crate::index::posting_list::PostingListIterator;
+use crate::query::quantizer::{QueryQuantizer, Rounding, Scaling};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
+use std::str::FromStr;
 
 pub const MAX_TERM_WEIGHT: usize = 32;
 
-pub fn cursors_from_queries<P: Into<PathBuf>>(
+/// A query mapping each term to its (unquantized) weight.
+pub type WeightedQuery = HashMap<String, f32>;
+
+/// Format of a queries file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryFormat {
+    /// `id: token token ...`, where the weight of a token is its number of occurrences.
+    #[default]
+    Pisa,
+    /// One `{"id": ..., "vector": {term: weight}}` object per line.
+    Jsonl,
+    /// `id<TAB>term:weight term:weight ...`, as produced by SPLADE tooling.
+    Tsv,
+}
+
+impl FromStr for QueryFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pisa" => Ok(QueryFormat::Pisa),
+            "jsonl" => Ok(QueryFormat::Jsonl),
+            "tsv" => Ok(QueryFormat::Tsv),
+            _ => Err(anyhow!(
+                "unknown query format: {} (expected pisa, jsonl or tsv)",
+                s
+            )),
+        }
+    }
+}
+
+impl QueryFormat {
+    /// Scaling matching the weights of the format: term counts are only scaled down, while
+    /// float weights are scaled per query.
+    pub fn default_scaling(self) -> Scaling {
+        match self {
+            QueryFormat::Pisa => Scaling::DownscaleOnly,
+            QueryFormat::Jsonl | QueryFormat::Tsv => Scaling::PerQuery,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonQuery {
+    id: serde_json::Value,
+    vector: WeightedQuery,
+}
+
+/// Returns the cursors of the query terms found in the index.
+pub fn cursors_from_quantized_query<'a>(
+    query: &HashMap<String, u32>,
+    index: &'a Index,
+) -> Vec<PostingListIterator<'a>> {
+    query
+        .iter()
+        .flat_map(|(token, freq)| index.get_cursor(token, *freq))
+        .collect()
+}
+
+/// Quantizes a float-weighted query and returns the cursors of its terms found in the index.
+pub fn cursors_from_weighted_query<'a>(
+    query: &WeightedQuery,
+    index: &'a Index,
+    quantizer: &QueryQuantizer,
+) -> Vec<PostingListIterator<'a>> {
+    cursors_from_quantized_query(&quantizer.quantize(query), index)
+}
+
+/// Counts the occurrences of each whitespace-separated token of the query.
+pub fn term_counts(query: &str) -> WeightedQuery {
+    let mut token_freqs = WeightedQuery::new();
+    for t in query.split_whitespace() {
+        *token_freqs.entry(t.to_string()).or_insert(0.0) += 1.0;
+    }
+    token_freqs
+}
+
+/// Reads queries in the given format. PISA queries are weighted by their term counts.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or a line is malformed.
+pub fn weighted_queries_from_file<P: Into<PathBuf>>(
     queries_file: P,
-    index: &Index,
-) -> (Vec<String>, Vec<Vec<PostingListIterator>>) {
+    format: QueryFormat,
+) -> Result<(Vec<String>, Vec<WeightedQuery>)> {
     let mut queries = Vec::new();
     let mut q_ids = Vec::new();
 
-    let file = File::open(queries_file.into()).expect("Error opening file");
+    let queries_file = queries_file.into();
+    let file = File::open(&queries_file)
+        .with_context(|| format!("Unable to open {}", queries_file.display()))?;
     let reader = BufReader::new(file);
 
     for (query_line, line) in reader.lines().enumerate() {
-        let line = line.expect("Error reading line from file");
-
-        // Split the line by ':' to separate the identifier and values
-        let parts: Vec<&str> = line.splitn(2, ':').collect();
-
-        if parts.len() == 2 {
-            // Parse the query ID
-            let q_id = parts[0].trim().to_string();
-            q_ids.push(q_id);
-
-            queries.push(cursors_from_query_text(parts[1], index));
-        } else {
-            panic!("Invalid line format in file at line {}", query_line);
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
         }
+        let (q_id, vector) = match format {
+            QueryFormat::Jsonl => {
+                let query: JsonQuery = serde_json::from_str(&line)
+                    .with_context(|| format!("Invalid JSON query at line {}", query_line))?;
+                let q_id = match query.id {
+                    serde_json::Value::String(id) => id,
+                    id => id.to_string(),
+                };
+                (q_id, query.vector)
+            }
+            QueryFormat::Tsv => {
+                let (q_id, terms) = line
+                    .split_once('\t')
+                    .ok_or_else(|| anyhow!("Invalid line format in file at line {}", query_line))?;
+                let vector = terms
+                    .split_whitespace()
+                    .map(|pair| {
+                        let (term, weight) = pair.rsplit_once(':').ok_or_else(|| {
+                            anyhow!("Invalid term:weight pair in file at line {}", query_line)
+                        })?;
+                        let weight = weight.parse::<f32>().with_context(|| {
+                            format!("Invalid weight in file at line {}", query_line)
+                        })?;
+                        Ok((term.to_string(), weight))
+                    })
+                    .collect::<Result<HashMap<_, _>>>()?;
+                (q_id.trim().to_string(), vector)
+            }
+            QueryFormat::Pisa => {
+                // Split the line by ':' to separate the identifier and values
+                let (q_id, terms) = line
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("Invalid line format in file at line {}", query_line))?;
+                (q_id.trim().to_string(), term_counts(terms))
+            }
+        };
+        q_ids.push(q_id);
+        queries.push(vector);
     }
-    (q_ids, queries)
+    Ok((q_ids, queries))
+}
+
+/// Reads the queries file in the given format, quantizes them and returns the ids and cursors of
+/// each query.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or a line is malformed.
+pub fn cursors_from_queries_with_format<'a, P: Into<PathBuf>>(
+    queries_file: P,
+    index: &'a Index,
+    format: QueryFormat,
+    quantizer: &QueryQuantizer,
+) -> Result<(Vec<String>, Vec<Vec<PostingListIterator<'a>>>)> {
+    let (q_ids, queries) = weighted_queries_from_file(queries_file, format)?;
+    let cursors = quantizer
+        .quantize_all(&queries)
+        .iter()
+        .map(|query| cursors_from_quantized_query(query, index))
+        .collect();
+    Ok((q_ids, cursors))
+}
+
+/// Like [`cursors_from_queries_with_format`], for PISA queries weighted by their term counts.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read.
+pub fn cursors_from_queries<P: Into<PathBuf>>(
+    queries_file: P,
+    index: &Index,
+) -> Result<(Vec<String>, Vec<Vec<PostingListIterator<'_>>>)> {
+    let quantizer = QueryQuantizer::new(
+        MAX_TERM_WEIGHT as u16,
+        Rounding::Ceil,
+        QueryFormat::Pisa.default_scaling(),
+    );
+    cursors_from_queries_with_format(queries_file, index, QueryFormat::Pisa, &quantizer)
 }
 
 pub fn cursors_from_query_text<'a>(query: &str, index: &'a Index) -> Vec<PostingListIterator<'a>> {
-    // Parse the values and create PostingList
-    let values: Vec<&str> = query.split_whitespace().collect();
-    let mut token_freqs: HashMap<String, u32> = HashMap::new();
-    for t in values {
-        *token_freqs.entry(t.to_string()).or_insert(0) += 1;
+    let quantizer = QueryQuantizer::new(
+        MAX_TERM_WEIGHT as u16,
+        Rounding::Ceil,
+        QueryFormat::Pisa.default_scaling(),
+    );
+    cursors_from_weighted_query(&term_counts(query), index, &quantizer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_term_counts() {
+        let counts = term_counts("a b  a\tc a");
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts["a"], 3.0);
+        assert_eq!(counts["c"], 1.0);
     }
-    let max_tok_weight = token_freqs.iter().map(|p| *p.1).max().unwrap();
-    if max_tok_weight > MAX_TERM_WEIGHT as u32 {
-        let scale: f32 = MAX_TERM_WEIGHT as f32 / max_tok_weight as f32;
-        for value in token_freqs.values_mut() {
-            *value = (*value as f32 * scale).ceil() as u32;
-        }
+
+    #[test]
+    fn test_query_format_from_str() {
+        assert_eq!("pisa".parse::<QueryFormat>().unwrap(), QueryFormat::Pisa);
+        assert_eq!("jsonl".parse::<QueryFormat>().unwrap(), QueryFormat::Jsonl);
+        assert_eq!("tsv".parse::<QueryFormat>().unwrap(), QueryFormat::Tsv);
+        assert!("csv".parse::<QueryFormat>().is_err());
     }
-    let cursors: Vec<PostingListIterator> = token_freqs
-        .iter()
-        .flat_map(|(token, freq)| index.get_cursor(token, *freq))
-        .collect();
-    cursors
 }
@@ -0,0 +1,175 @@
+use crate::index::posting_list::PostingListIterator;
+use anyhow::{anyhow, Result};
+use std::cmp::Reverse;
+use std::str::FromStr;
+
+/// Strategy selecting the query terms to keep before the search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueryPruning {
+    /// Keeps the `ceil(len * fraction)` terms with the largest weights, like the `beta` parameter
+    /// of the search.
+    TopFraction(f32),
+    /// Keeps at most the given number of terms with the largest weights.
+    MaxTerms(usize),
+    /// Keeps the terms with the largest weights until they account for the given fraction of the
+    /// total query weight.
+    WeightMass(f32),
+    /// Keeps the terms with the largest maximum contribution (weight × maximum impact) until they
+    /// account for the given fraction of the total maximum contribution.
+    ContributionMass(f32),
+    /// Drops the terms appearing in more than the given fraction of the documents.
+    MaxDocumentFrequency(f32),
+}
+
+impl FromStr for QueryPruning {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (strategy, value) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("invalid query pruning: {} (expected <strategy>:<value>)", s))?;
+        let fraction = || {
+            value
+                .parse::<f32>()
+                .ok()
+                .filter(|f| (0.0..=1.0).contains(f))
+                .ok_or_else(|| anyhow!("invalid fraction in query pruning: {}", s))
+        };
+        match strategy {
+            "top-fraction" => Ok(QueryPruning::TopFraction(fraction()?)),
+            "max-terms" => Ok(QueryPruning::MaxTerms(value.parse().map_err(|_| {
+                anyhow!("invalid number of terms in query pruning: {}", s)
+            })?)),
+            "weight-mass" => Ok(QueryPruning::WeightMass(fraction()?)),
+            "contribution-mass" => Ok(QueryPruning::ContributionMass(fraction()?)),
+            "max-df" => Ok(QueryPruning::MaxDocumentFrequency(fraction()?)),
+            _ => Err(anyhow!(
+                "unknown query pruning strategy: {} (expected top-fraction, max-terms, weight-mass, contribution-mass or max-df)",
+                strategy
+            )),
+        }
+    }
+}
+
+impl QueryPruning {
+    /// Removes the pruned terms from the query. The remaining terms are sorted by decreasing
+    /// priority, except with `MaxDocumentFrequency` which keeps the original order.
+    pub fn prune(&self, query: &mut Vec<PostingListIterator>, num_documents: usize) {
+        match *self {
+            QueryPruning::TopFraction(fraction) => {
+                let terms_to_keep = (query.len() as f32 * fraction).ceil() as usize;
+                sort_by_key_desc(query, |pl| pl.term_weight() as u32);
+                query.truncate(terms_to_keep);
+            }
+            QueryPruning::MaxTerms(terms_to_keep) => {
+                sort_by_key_desc(query, |pl| pl.term_weight() as u32);
+                query.truncate(terms_to_keep);
+            }
+            QueryPruning::WeightMass(fraction) => {
+                truncate_to_mass(query, fraction, |pl| pl.term_weight() as u32)
+            }
+            QueryPruning::ContributionMass(fraction) => truncate_to_mass(query, fraction, |pl| {
+                pl.term_weight() as u32 * pl.max_impact() as u32
+            }),
+            QueryPruning::MaxDocumentFrequency(fraction) => {
+                let max_df = fraction * num_documents as f32;
+                query.retain(|pl| pl.doc_freq() as f32 <= max_df);
+            }
+        }
+    }
+}
+
+fn sort_by_key_desc<F>(query: &mut [PostingListIterator], key: F)
+where
+    F: Fn(&PostingListIterator) -> u32,
+{
+    query.sort_by_key(|pl| Reverse(key(pl)));
+}
+
+/// Keeps the shortest prefix of terms, by decreasing `key`, whose keys sum to at least `fraction`
+/// of the total.
+fn truncate_to_mass<F>(query: &mut Vec<PostingListIterator>, fraction: f32, key: F)
+where
+    F: Fn(&PostingListIterator) -> u32,
+{
+    sort_by_key_desc(query, &key);
+    let total: u64 = query.iter().map(|pl| key(pl) as u64).sum();
+    let target = total as f64 * fraction as f64;
+    let mut mass = 0;
+    let mut terms_to_keep = 0;
+    while terms_to_keep < query.len() && (mass as f64) < target {
+        mass += key(&query[terms_to_keep]) as u64;
+        terms_to_keep += 1;
+    }
+    query.truncate(terms_to_keep);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index::posting_list::{BlockData, PostingList, SuperblockMaxes};
+
+    fn posting_lists() -> Vec<PostingList> {
+        // (doc_freq, max_impact) of each term
+        [(100, 10), (10, 200), (50, 50), (1, 1)]
+            .iter()
+            .map(|&(df, max)| {
+                let range_maxes = vec![max];
+                let superblock_maxes = SuperblockMaxes::new(&range_maxes);
+                PostingList::new(
+                    BlockData::Raw(range_maxes),
+                    superblock_maxes,
+                    vec![0; 3],
+                    df,
+                    max,
+                )
+            })
+            .collect()
+    }
+
+    fn prune(pruning: QueryPruning, posting_lists: &[PostingList]) -> Vec<u32> {
+        let weights = [8, 4, 2, 1];
+        let mut query: Vec<_> = posting_lists
+            .iter()
+            .zip(weights)
+            .enumerate()
+            .map(|(term_id, (pl, weight))| pl.iter(term_id as u32, weight))
+            .collect();
+        query.reverse();
+        pruning.prune(&mut query, 100);
+        query.iter().map(|pl| pl.term_id()).collect()
+    }
+
+    #[test]
+    fn test_prune() {
+        let pls = posting_lists();
+        assert_eq!(prune(QueryPruning::TopFraction(0.5), &pls), vec![0, 1]);
+        assert_eq!(
+            prune(QueryPruning::TopFraction(1.0), &pls),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(prune(QueryPruning::MaxTerms(3), &pls), vec![0, 1, 2]);
+        assert_eq!(prune(QueryPruning::WeightMass(0.75), &pls), vec![0, 1]);
+        assert_eq!(prune(QueryPruning::WeightMass(0.85), &pls), vec![0, 1, 2]);
+        assert_eq!(prune(QueryPruning::ContributionMass(0.5), &pls), vec![1]);
+        assert_eq!(
+            prune(QueryPruning::MaxDocumentFrequency(0.5), &pls),
+            vec![3, 2, 1]
+        );
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "max-terms:10".parse::<QueryPruning>().unwrap(),
+            QueryPruning::MaxTerms(10)
+        );
+        assert_eq!(
+            "contribution-mass:0.9".parse::<QueryPruning>().unwrap(),
+            QueryPruning::ContributionMass(0.9)
+        );
+        assert!("weight-mass:1.5".parse::<QueryPruning>().is_err());
+        assert!("max-terms".parse::<QueryPruning>().is_err());
+        assert!("unknown:1".parse::<QueryPruning>().is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::query::{WeightedQuery, MAX_TERM_WEIGHT};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Rounding applied to scaled query weights.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    #[default]
+    Ceil,
+    Round,
+    Floor,
+}
+
+impl Rounding {
+    fn apply(self, value: f32) -> f32 {
+        match self {
+            Rounding::Ceil => value.ceil(),
+            Rounding::Round => value.round(),
+            Rounding::Floor => value.floor(),
+        }
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ceil" => Ok(Rounding::Ceil),
+            "round" => Ok(Rounding::Round),
+            "floor" => Ok(Rounding::Floor),
+            _ => Err(anyhow!(
+                "unknown rounding: {} (expected ceil, round or floor)",
+                s
+            )),
+        }
+    }
+}
+
+/// How query weights are scaled before rounding.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Scaling {
+    /// Each query is scaled so that its largest weight becomes the maximum weight.
+    #[default]
+    PerQuery,
+    /// Like `PerQuery`, but queries whose weights are already within the maximum weight are left
+    /// unscaled. This is how term counts of PISA queries are handled.
+    DownscaleOnly,
+    /// Every query is scaled by the same factor, mapping the given weight to the maximum weight;
+    /// larger weights are clipped. With `None`, the largest weight among all the queries quantized
+    /// together is used.
+    Global(Option<f32>),
+}
+
+impl FromStr for Scaling {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "per-query" => Ok(Scaling::PerQuery),
+            "downscale-only" => Ok(Scaling::DownscaleOnly),
+            "global" => Ok(Scaling::Global(None)),
+            _ => match s.strip_prefix("global:").map(str::parse::<f32>) {
+                Some(Ok(weight)) if weight > 0.0 => Ok(Scaling::Global(Some(weight))),
+                _ => Err(anyhow!(
+                    "unknown scaling: {} (expected per-query, downscale-only, global or global:<weight>)",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// Converts float query weights into the integer weights used by the search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueryQuantizer {
+    max_weight: u16,
+    rounding: Rounding,
+    scaling: Scaling,
+}
+
+impl Default for QueryQuantizer {
+    fn default() -> Self {
+        QueryQuantizer::new(
+            MAX_TERM_WEIGHT as u16,
+            Rounding::default(),
+            Scaling::default(),
+        )
+    }
+}
+
+impl QueryQuantizer {
+    /// Creates a quantizer producing weights in `1..=max_weight`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_weight` is zero.
+    pub fn new(max_weight: u16, rounding: Rounding, scaling: Scaling) -> Self {
+        assert!(max_weight > 0, "max_weight must be positive");
+        QueryQuantizer {
+            max_weight,
+            rounding,
+            scaling,
+        }
+    }
+
+    pub fn max_weight(&self) -> u16 {
+        self.max_weight
+    }
+
+    pub fn rounding(&self) -> Rounding {
+        self.rounding
+    }
+
+    pub fn scaling(&self) -> Scaling {
+        self.scaling
+    }
+
+    /// Quantizes a single query. Terms with a non-positive (or NaN) weight, or rounded to zero,
+    /// are dropped.
+    pub fn quantize(&self, query: &WeightedQuery) -> HashMap<String, u32> {
+        self.quantize_with_reference(query, max_weight(query))
+    }
+
+    /// Quantizes a set of queries, sharing the reference weight among them with
+    /// `Scaling::Global(None)`.
+    pub fn quantize_all(&self, queries: &[WeightedQuery]) -> Vec<HashMap<String, u32>> {
+        let global_max = match self.scaling {
+            Scaling::Global(None) => queries.iter().map(max_weight).fold(0.0, f32::max),
+            _ => 0.0,
+        };
+        queries
+            .iter()
+            .map(|query| match self.scaling {
+                Scaling::Global(None) => self.quantize_with_reference(query, global_max),
+                _ => self.quantize(query),
+            })
+            .collect()
+    }
+
+    fn quantize_with_reference(
+        &self,
+        query: &WeightedQuery,
+        query_max: f32,
+    ) -> HashMap<String, u32> {
+        let max_weight = self.max_weight as f32;
+        let reference = match self.scaling {
+            Scaling::PerQuery | Scaling::Global(None) => query_max,
+            Scaling::DownscaleOnly => query_max.max(max_weight),
+            Scaling::Global(Some(reference)) => reference,
+        };
+        if reference <= 0.0 {
+            return HashMap::new();
+        }
+        let scale = max_weight / reference;
+        query
+            .iter()
+            .filter(|(_, &value)| value > 0.0)
+            .map(|(term, &value)| {
+                let weight = self.rounding.apply(value * scale).min(max_weight);
+                (term.clone(), weight as u32)
+            })
+            .filter(|&(_, weight)| weight > 0)
+            .collect()
+    }
+}
+
+/// Largest positive weight of the query, or zero.
+fn max_weight(query: &WeightedQuery) -> f32 {
+    query
+        .values()
+        .copied()
+        .filter(|&value| value > 0.0)
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn query(weights: &[(&str, f32)]) -> WeightedQuery {
+        weights.iter().map(|&(t, w)| (t.to_string(), w)).collect()
+    }
+
+    #[test]
+    fn test_per_query() {
+        let q = query(&[("a", 2.0), ("b", 0.5), ("c", 0.0), ("d", f32::NAN)]);
+        let quantized = QueryQuantizer::default().quantize(&q);
+        assert_eq!(quantized.len(), 2);
+        assert_eq!(quantized["a"], MAX_TERM_WEIGHT as u32);
+        assert_eq!(quantized["b"], 8);
+        assert!(QueryQuantizer::default()
+            .quantize(&WeightedQuery::new())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rounding() {
+        let q = query(&[("a", 10.0), ("b", 0.3)]);
+        let quantize = |rounding| QueryQuantizer::new(10, rounding, Scaling::PerQuery).quantize(&q);
+        assert_eq!(quantize(Rounding::Ceil)["b"], 1);
+        assert_eq!(quantize(Rounding::Round).get("b"), None);
+        assert_eq!(quantize(Rounding::Floor).get("b"), None);
+    }
+
+    #[test]
+    fn test_downscale_only() {
+        let quantizer = QueryQuantizer::new(32, Rounding::Ceil, Scaling::DownscaleOnly);
+        let q = query(&[("a", 3.0), ("b", 1.0)]);
+        assert_eq!(quantizer.quantize(&q)["a"], 3);
+        let q = query(&[("a", 64.0), ("b", 3.0)]);
+        assert_eq!(quantizer.quantize(&q)["a"], 32);
+        assert_eq!(quantizer.quantize(&q)["b"], 2);
+    }
+
+    #[test]
+    fn test_global() {
+        let queries = [query(&[("a", 4.0)]), query(&[("a", 1.0), ("b", 2.0)])];
+        let quantizer = QueryQuantizer::new(8, Rounding::Ceil, Scaling::Global(None));
+        let quantized = quantizer.quantize_all(&queries);
+        assert_eq!(quantized[0]["a"], 8);
+        assert_eq!(quantized[1]["a"], 2);
+        assert_eq!(quantized[1]["b"], 4);
+
+        let quantizer = QueryQuantizer::new(8, Rounding::Ceil, Scaling::Global(Some(2.0)));
+        assert_eq!(quantizer.quantize(&queries[0])["a"], 8);
+        assert_eq!(quantizer.quantize(&queries[1])["a"], 4);
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("round".parse::<Rounding>().unwrap(), Rounding::Round);
+        assert!("up".parse::<Rounding>().is_err());
+        assert_eq!(
+            "global:2.5".parse::<Scaling>().unwrap(),
+            Scaling::Global(Some(2.5))
+        );
+        assert_eq!("global".parse::<Scaling>().unwrap(), Scaling::Global(None));
+        assert!("global:x".parse::<Scaling>().is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use crate::index::posting_list::PostingListIterator;
+use crate::index::simd;
+use std::fmt::{Debug, Display};
+use std::ops::AddAssign;
+
+/// Integer type in which document scores and block upper bounds are accumulated.
+///
+/// `u16` is the default and is enough for 8-bit query weights as long as scores stay within
+/// `u16::MAX`; `u32` allows for 16-bit query weights and larger scores at the cost of twice the
+/// memory for upper bounds. See [`needs_wide_scores`].
+pub trait Score:
+    Copy + Default + Ord + AddAssign + Display + Debug + Send + Sync + 'static
+{
+    /// Number of bits of the score type.
+    const BITS: u32;
+    /// Largest query weight whose product with any impact fits in a single score.
+    const MAX_WEIGHT: u16;
+
+    /// Contribution of a term with query weight `weight` (at most `MAX_WEIGHT`) and `impact`.
+    fn product(weight: u16, impact: u8) -> Self;
+    /// Adds the contribution of `weight` with each impact to the corresponding score.
+    fn accumulate(scores: &mut [Self], impacts: &[u8], weight: u16);
+    /// Like `accumulate`, but saturating.
+    fn accumulate_saturating(scores: &mut [Self], values: &[u8], weight: u16);
+    fn saturating_add(self, other: Self) -> Self;
+    fn saturating_sub(self, other: Self) -> Self;
+    fn as_usize(self) -> usize;
+    fn to_f32(self) -> f32;
+}
+
+impl Score for u16 {
+    const BITS: u32 = u16::BITS;
+    const MAX_WEIGHT: u16 = u8::MAX as u16;
+
+    #[inline]
+    fn product(weight: u16, impact: u8) -> Self {
+        weight * impact as u16
+    }
+
+    #[inline]
+    fn accumulate(scores: &mut [Self], impacts: &[u8], weight: u16) {
+        simd::accumulate_u16(scores, impacts, weight)
+    }
+
+    #[inline]
+    fn accumulate_saturating(scores: &mut [Self], values: &[u8], weight: u16) {
+        simd::accumulate_saturating_u16(scores, values, weight)
+    }
+
+    #[inline]
+    fn saturating_add(self, other: Self) -> Self {
+        u16::saturating_add(self, other)
+    }
+
+    #[inline]
+    fn saturating_sub(self, other: Self) -> Self {
+        u16::saturating_sub(self, other)
+    }
+
+    #[inline]
+    fn as_usize(self) -> usize {
+        self as usize
+    }
+
+    #[inline]
+    fn to_f32(self) -> f32 {
+        self.into()
+    }
+}
+
+impl Score for u32 {
+    const BITS: u32 = u32::BITS;
+    const MAX_WEIGHT: u16 = u16::MAX;
+
+    #[inline]
+    fn product(weight: u16, impact: u8) -> Self {
+        weight as u32 * impact as u32
+    }
+
+    #[inline]
+    fn accumulate(scores: &mut [Self], impacts: &[u8], weight: u16) {
+        simd::accumulate_u32(scores, impacts, weight)
+    }
+
+    #[inline]
+    fn accumulate_saturating(scores: &mut [Self], values: &[u8], weight: u16) {
+        simd::accumulate_saturating_u32(scores, values, weight)
+    }
+
+    #[inline]
+    fn saturating_add(self, other: Self) -> Self {
+        u32::saturating_add(self, other)
+    }
+
+    #[inline]
+    fn saturating_sub(self, other: Self) -> Self {
+        u32::saturating_sub(self, other)
+    }
+
+    #[inline]
+    fn as_usize(self) -> usize {
+        self as usize
+    }
+
+    #[inline]
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+/// Whether the scores of `query` must be accumulated in `u32` rather than `u16`: when a weight
+/// exceeds 8 bits, or when the largest score a document can get, the sum of the products of the
+/// weights with the largest impacts of their terms, exceeds `u16::MAX`.
+pub fn needs_wide_scores(query: &[PostingListIterator<'_>]) -> bool {
+    let max_score: u64 = query
+        .iter()
+        .map(|cursor| u64::from(cursor.term_weight()) * u64::from(cursor.max_impact()))
+        .sum();
+    query
+        .iter()
+        .any(|cursor| cursor.term_weight() > <u16 as Score>::MAX_WEIGHT)
+        || max_score > u64::from(u16::MAX)
+}
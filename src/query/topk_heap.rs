@@ -76,7 +76,7 @@ impl<S: Default + Copy + PartialOrd> TopKHeap<S> {
     /// Returns the new threshold if the heap size reaches or exceeds k, otherwise returns `None`.
     pub fn insert(&mut self, doc_id: DocId, score: S) -> Option<S> {
         // Check if the score qualifies for entry into the top-k heap.
-        if !self.would_enter(score) {
+        if self.k == 0 || !self.would_enter(score) {
             return None;
         }
         // Push the new entry into the heap.
@@ -1,9 +1,11 @@
 use crate::index::forward_index::block_score;
-use crate::index::forward_index::BlockForwardIndex;
-use crate::index::posting_list::PostingListIterator;
+use crate::index::forward_index::{BlockForwardIndex, ForwardIndex};
+use crate::index::posting_list::{PostingListIterator, SUPERBLOCK_SIZE};
+use crate::query::candidates::CandidateBlocks;
 use crate::query::cursor::DocId;
 use crate::query::cursor::{RangeMaxScore, RangeMaxScoreCursor};
 use crate::query::live_block;
+use crate::query::score::Score;
 use crate::query::topk_heap::TopKHeap;
 use crate::util::progress_bar;
 use std::time::Instant;
@@ -14,12 +16,11 @@ use std::arch::aarch64::{_prefetch, _PREFETCH_LOCALITY0, _PREFETCH_READ};
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
 
-
 #[cfg(target_arch = "x86_64")]
 fn prefetch_block(forward_index: &BlockForwardIndex, block: u32) {
     unsafe {
         _mm_prefetch(
-            forward_index.data.as_ptr().add(block as usize) as *const i8,
+            forward_index.block(block as usize).as_ptr() as *const i8,
             _MM_HINT_T0,
         );
     }
@@ -29,33 +30,62 @@ fn prefetch_block(forward_index: &BlockForwardIndex, block: u32) {
 fn prefetch_block(forward_index: &BlockForwardIndex, block: u32) {
     unsafe {
         _prefetch(
-            forward_index.data.as_ptr().add(block as usize) as *const i8,
+            forward_index.block(block as usize).as_ptr() as *const i8,
             _PREFETCH_READ,
             _PREFETCH_LOCALITY0,
         );
     }
 }
 
+/// Statistics of the processing of a query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Number of blocks whose upper bound exceeds the initial threshold.
+    pub candidate_blocks: usize,
+    /// Number of blocks scored before the search stopped.
+    pub scored_blocks: usize,
+    /// Whether no block can contain a result, because none of the query terms is in the index or
+    /// no upper bound exceeds the initial threshold. The results are then empty.
+    pub no_candidates: bool,
+}
 
-pub fn b_search(
+pub fn b_search<S: Score>(
     queries: Vec<Vec<PostingListIterator>>,
     forward_index: &BlockForwardIndex,
     k: usize,
     alpha: f32,
     terms_r: f32,
-) -> Vec<TopKHeap<u16>> {
+) -> Vec<TopKHeap<S>> {
     b_search_verbose(queries, forward_index, k, alpha, terms_r, true)
 }
 
-pub fn b_search_verbose(
+/// Retrieves the top-`k` documents of each query.
+///
+/// Scores are accumulated in `S`; query weights larger than `S::MAX_WEIGHT` are clipped. Scores
+/// that do not fit in `S` wrap around, so `u32` must be used when
+/// [`needs_wide_scores`](crate::query::score::needs_wide_scores) holds.
+pub fn b_search_verbose<S: Score>(
+    queries: Vec<Vec<PostingListIterator>>,
+    forward_index: &BlockForwardIndex,
+    k: usize,
+    alpha: f32,
+    terms_r: f32,
+    verbose: bool,
+) -> Vec<TopKHeap<S>> {
+    b_search_with_stats(queries, forward_index, k, alpha, terms_r, verbose).0
+}
+
+/// Like [`b_search_verbose`], but also returns the statistics of each query.
+pub fn b_search_with_stats<S: Score>(
     mut queries: Vec<Vec<PostingListIterator>>,
     forward_index: &BlockForwardIndex,
     k: usize,
     alpha: f32,
     terms_r: f32,
     verbose: bool,
-) -> Vec<TopKHeap<u16>> {
-    let mut results: Vec<TopKHeap<u16>> = Vec::new();
+) -> (Vec<TopKHeap<S>>, Vec<QueryStats>) {
+    let mut results: Vec<TopKHeap<S>> = Vec::new();
+    let mut stats: Vec<QueryStats> = Vec::new();
 
     let progress = if verbose {
         Some(progress_bar("Forward index-based search", queries.len()))
@@ -64,7 +94,6 @@ pub fn b_search_verbose(
     };
 
     let mut search_elapsed = 0;
-    let mut buckets: Vec<Vec<u32>> = (0..=2usize.pow(16)).map(|_| Vec::new()).collect();
 
     for query in queries.iter_mut() {
         let total_terms = query.len();
@@ -73,7 +102,10 @@ pub fn b_search_verbose(
         // Keep only the top N terms
         query.truncate(terms_to_keep);
 
-        let query_weights: Vec<_> = query.iter().map(|post| post.term_weight()).collect();
+        let query_weights: Vec<_> = query
+            .iter()
+            .map(|post| post.term_weight().min(S::MAX_WEIGHT))
+            .collect();
 
         let query_ranges: Vec<_> = query.iter().map(|post| post.range_max_scores()).collect();
         let mut query_ranges_raw = Vec::new();
@@ -87,72 +119,82 @@ pub fn b_search_verbose(
 
         let mut query_vec = query
             .iter()
-            .map(|&pl| (pl.term_id() as u16, pl.term_weight() as u8))
+            .map(|&pl| (pl.term_id(), pl.term_weight().min(S::MAX_WEIGHT)))
             .collect::<Vec<_>>();
         query_vec.sort_by_key(|e| e.0);
+        // At least k documents score as much as the k-th impact of any term, so the k-th score is at
+        // least that much, and documents with exactly that score may be part of the results.
         let threshold = query
             .iter()
-            .map(|&pl| pl.kth(k) as u16 * pl.term_weight() as u16)
+            .map(|&pl| S::product(pl.term_weight().min(S::MAX_WEIGHT), pl.kth(k)))
             .max()
-            .unwrap_or(0);
+            .unwrap_or_default()
+            .saturating_sub(S::product(1, 1));
 
         let start_search: Instant = Instant::now();
-        let run_compressed = query_ranges_compressed.len() > 0;
-        let upper_bounds = match run_compressed {
+        // Superblocks whose upper bound does not exceed the threshold cannot contain any result,
+        // so the upper bounds of their blocks are not computed.
+        let query_maxes: Vec<_> = query.iter().map(|pl| pl.superblock_maxes()).collect();
+        let live_superblocks: Vec<bool> = live_block::compute_superblock_upper_bounds::<S>(
+            &query_maxes,
+            &query_weights,
+            forward_index.num_blocks().div_ceil(SUPERBLOCK_SIZE),
+        )
+        .into_iter()
+        .map(|ub| ub > threshold)
+        .collect();
+
+        let run_compressed = !query_ranges_compressed.is_empty();
+        let upper_bounds: Vec<S> = match run_compressed {
             true => live_block::compute_upper_bounds(
                 &query_ranges_compressed,
                 &query_weights,
-                forward_index.data.len(),
+                forward_index.num_blocks(),
+                &live_superblocks,
             ),
             false => live_block::compute_upper_bounds_raw(
                 &query_ranges_raw,
                 &query_weights,
-                forward_index.data.len(),
+                forward_index.num_blocks(),
+                &live_superblocks,
             ),
         };
 
-        let mut topk = TopKHeap::with_threshold(k, threshold as u16);
-        buckets.iter_mut().for_each(std::vec::Vec::clear);
-        upper_bounds.iter().enumerate().for_each(|(range_id, &ub)| {
-            if ub > threshold {
-                buckets[ub as usize].push(range_id as u32);
-            }
-        });
-
-        let mut ub_iter =
-            buckets
-                .iter_mut()
-                .enumerate()
-                .rev()
-                .flat_map(|(outer_idx, inner_vec)| {
-                    inner_vec.iter_mut().map(move |val| (outer_idx, val))
-                });
-
-        let (mut current_ub, mut current_block) = ub_iter.next().unwrap();
-        prefetch_block(forward_index, *current_block);
+        let mut topk = TopKHeap::with_threshold(k, threshold);
+        let mut candidates = CandidateBlocks::new(&upper_bounds, threshold);
+        let mut query_stats = QueryStats {
+            candidate_blocks: candidates.len(),
+            no_candidates: candidates.is_empty(),
+            ..Default::default()
+        };
 
-        for (next_ub, next_block) in ub_iter {
-            prefetch_block(forward_index, *next_block);
-            let offset = *current_block as usize * forward_index.block_size;
+        let mut next = candidates.next();
+        if let Some((_, block)) = next {
+            prefetch_block(forward_index, block);
+        }
+        while let Some((ub, block)) = next {
+            if topk.threshold().to_f32() > ub.to_f32() * alpha {
+                break;
+            }
+            next = candidates.next();
+            if let Some((_, next_block)) = next {
+                prefetch_block(forward_index, next_block);
+            }
 
+            let offset = block as usize * forward_index.block_size;
             let res = block_score(
                 &query_vec,
-                &forward_index.data[*current_block as usize],
+                forward_index.block(block as usize),
                 forward_index.block_size,
             );
-
             for (doc_id, &score) in res.iter().enumerate() {
                 topk.insert(DocId(doc_id as u32 + offset as u32), score);
             }
-
-            if topk.threshold() as f32 > current_ub as f32 * alpha {
-                break;
-            }
-            current_block = next_block;
-            current_ub = next_ub;
+            query_stats.scored_blocks += 1;
         }
         search_elapsed += start_search.elapsed().as_micros();
-        results.push(topk.clone());
+        results.push(topk);
+        stats.push(query_stats);
         if let Some(progress_bar) = &progress {
             progress_bar.inc(1);
         }
@@ -162,11 +204,370 @@ pub fn b_search_verbose(
     }
 
     if verbose {
-        eprintln!(
+        log::info!(
             "search_elapsed = {}",
-            search_elapsed / results.len() as u128
+            search_elapsed / results.len().max(1) as u128
         );
+        let no_candidates = stats.iter().filter(|s| s.no_candidates).count();
+        if no_candidates > 0 {
+            log::info!("{} queries without candidate blocks", no_candidates);
+        }
+    }
+
+    (results, stats)
+}
+
+/// Forward index whose documents can be scored one by one, for [`exhaustive`].
+pub trait ScoreDocuments {
+    /// Score of every document for a query made of `(term id, weight)` pairs sorted by term id.
+    fn score_documents<S: Score>(&self, query: &[(u32, u16)]) -> Vec<S>;
+}
+
+impl ScoreDocuments for ForwardIndex {
+    fn score_documents<S: Score>(&self, query: &[(u32, u16)]) -> Vec<S> {
+        self.into_iter()
+            .map(|document| {
+                let mut score = S::default();
+                for &(term_id, impact) in document {
+                    if let Ok(i) = query.binary_search_by_key(&term_id, |&(t, _)| t) {
+                        score += S::product(query[i].1, impact as u8);
+                    }
+                }
+                score
+            })
+            .collect()
+    }
+}
+
+impl ScoreDocuments for BlockForwardIndex {
+    fn score_documents<S: Score>(&self, query: &[(u32, u16)]) -> Vec<S> {
+        let mut scores = vec![S::default(); self.num_blocks() * self.block_size];
+        for block_id in 0..self.num_blocks() {
+            let offset = block_id * self.block_size;
+            for (term_id, postings) in self.block_terms(block_id) {
+                if let Ok(i) = query.binary_search_by_key(&term_id, |&(t, _)| t) {
+                    for (doc, impact) in postings.iter() {
+                        scores[offset + doc as usize] += S::product(query[i].1, impact);
+                    }
+                }
+            }
+        }
+        scores
+    }
+}
+
+/// Retrieves the top-`k` documents of each query by scoring every document of the forward index.
+///
+/// This is the reference for [`b_search`], which returns the same scores with `alpha` and
+/// `terms_r` set to 1, although documents with equal scores may differ.
+pub fn exhaustive<S: Score, F: ScoreDocuments>(
+    queries: &[Vec<PostingListIterator>],
+    forward_index: &F,
+    k: usize,
+) -> Vec<TopKHeap<S>> {
+    queries
+        .iter()
+        .map(|query| {
+            let mut query_vec: Vec<_> = query
+                .iter()
+                .map(|pl| (pl.term_id(), pl.term_weight().min(S::MAX_WEIGHT)))
+                .collect();
+            query_vec.sort_by_key(|e| e.0);
+            let mut topk = TopKHeap::new(k);
+            for (doc_id, score) in forward_index
+                .score_documents::<S>(&query_vec)
+                .into_iter()
+                .enumerate()
+            {
+                topk.insert(DocId(doc_id as u32), score);
+            }
+            topk
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index::forward_index::{fwd2bfwd, ForwardIndexBuilder};
+    use crate::index::inverted_index::{Index, IndexBuilder};
+    use crate::query::cursors_from_query_text;
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cmp::Reverse;
+    use std::collections::BTreeMap;
+
+    fn forward_index(documents: &[Vec<(u32, u32)>]) -> ForwardIndex {
+        let mut builder = ForwardIndexBuilder::new(0);
+        for document in documents {
+            builder.insert_document(document.clone());
+        }
+        builder.build()
     }
 
-    results
+    /// Index of documents given as `(term, impact)` pairs, where term `i` is named `t{i}`.
+    fn build_index(
+        documents: &[Vec<(u32, u32)>],
+        bsize: usize,
+        compress_range: bool,
+    ) -> (Index, BlockForwardIndex) {
+        let num_terms = documents.iter().flatten().map(|&(t, _)| t + 1).max();
+        let mut builder = IndexBuilder::new(documents.len(), bsize);
+        for term_id in 0..num_terms.unwrap_or(0) {
+            builder.insert_term(&format!("t{}", term_id), Vec::new());
+        }
+        for (doc_id, document) in documents.iter().enumerate() {
+            builder.insert_document(&format!("d{}", doc_id));
+            for &(term_id, impact) in document {
+                builder.push_posting(term_id, doc_id as u32, impact);
+            }
+        }
+        (
+            builder.build(compress_range),
+            fwd2bfwd(&forward_index(documents), bsize),
+        )
+    }
+
+    /// Documents and scores of the results, by decreasing score and increasing document, and the
+    /// statistics of the query.
+    fn search(
+        (index, bfwd): &(Index, BlockForwardIndex),
+        query: &str,
+        k: usize,
+    ) -> (Vec<(u32, u16)>, QueryStats) {
+        let cursors = cursors_from_query_text(query, index);
+        let (mut results, stats) =
+            b_search_with_stats::<u16>(vec![cursors], bfwd, k, 1.0, 1.0, false);
+        let mut results: Vec<_> = results[0]
+            .to_sorted_vec()
+            .iter()
+            .map(|e| (e.doc_id.0, e.score))
+            .collect();
+        // Ties are in any order.
+        results.sort_by_key(|&(doc_id, score)| (Reverse(score), doc_id));
+        (results, stats[0])
+    }
+
+    fn documents() -> Vec<Vec<(u32, u32)>> {
+        vec![
+            vec![(0, 3), (1, 1)],
+            vec![(1, 5)],
+            vec![(0, 1), (2, 7)],
+            vec![(0, 2), (1, 2)],
+            vec![(2, 1)],
+        ]
+    }
+
+    #[test]
+    fn test_empty_query() {
+        let index = build_index(&documents(), 2, false);
+        assert!(cursors_from_query_text("", &index.0).is_empty());
+        let (results, stats) = search(&index, "", 10);
+        assert!(results.is_empty());
+        assert!(stats.no_candidates);
+        assert_eq!(stats.scored_blocks, 0);
+    }
+
+    #[test]
+    fn test_out_of_vocabulary_query() {
+        let index = build_index(&documents(), 2, false);
+        let (results, stats) = search(&index, "foo bar", 10);
+        assert!(results.is_empty());
+        assert!(stats.no_candidates);
+    }
+
+    #[test]
+    fn test_k_larger_than_collection() {
+        let index = build_index(&documents(), 2, false);
+        let (results, stats) = search(&index, "t0 t1", 100);
+        assert_eq!(results, vec![(1, 5), (0, 4), (3, 4), (2, 1)]);
+        assert!(!stats.no_candidates);
+        assert_eq!(stats.candidate_blocks, 2);
+        assert_eq!(stats.scored_blocks, 2);
+
+        let (results, _) = search(&index, "t0", 0);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_single_block_collection() {
+        let index = build_index(&documents(), 8, false);
+        assert_eq!(index.1.num_blocks(), 1);
+        let (results, stats) = search(&index, "t2 t2 t1", 2);
+        assert_eq!(results, vec![(2, 14), (1, 5)]);
+        assert_eq!(stats.candidate_blocks, 1);
+        assert_eq!(stats.scored_blocks, 1);
+
+        let index = build_index(&[vec![(0, 1)]], 8, true);
+        assert_eq!(search(&index, "t0", 10).0, vec![(0, 1)]);
+    }
+
+    #[test]
+    fn test_wide_scores() {
+        use crate::query::cursors_from_weighted_query;
+        use crate::query::quantizer::{QueryQuantizer, Rounding, Scaling};
+        use crate::query::score::needs_wide_scores;
+        use crate::query::WeightedQuery;
+
+        // Two terms of maximum weight with maximum impacts, whose sum overflows 16 bits.
+        let index = build_index(&[vec![(0, 255), (1, 255)], vec![(0, 1)]], 2, false);
+        let quantizer = QueryQuantizer::new(255, Rounding::Ceil, Scaling::PerQuery);
+        let query = WeightedQuery::from([("t0".to_string(), 1.0), ("t1".to_string(), 1.0)]);
+        let cursors = cursors_from_weighted_query(&query, &index.0, &quantizer);
+        assert!(needs_wide_scores(&cursors));
+        assert!(!needs_wide_scores(&cursors[..1]));
+        let mut results = b_search_verbose::<u32>(vec![cursors], &index.1, 10, 1.0, 1.0, false);
+        let results: Vec<_> = results[0]
+            .to_sorted_vec()
+            .iter()
+            .map(|e| (e.doc_id.0, e.score))
+            .collect();
+        assert_eq!(results, vec![(0, 2 * 255 * 255), (1, 255)]);
+
+        // Weights wider than 8 bits.
+        let quantizer = QueryQuantizer::new(256, Rounding::Ceil, Scaling::PerQuery);
+        let query = WeightedQuery::from([("t0".to_string(), 1.0)]);
+        assert!(needs_wide_scores(&cursors_from_weighted_query(
+            &query, &index.0, &quantizer
+        )));
+
+        // Weights wider than 16 bits saturate rather than wrap.
+        let cursor = index.0.get_cursor("t1", u16::MAX as u32 + 2).unwrap();
+        assert_eq!(cursor.term_weight(), u16::MAX);
+        let max_score = u16::MAX as u32 * cursor.max_impact() as u32;
+        let mut results =
+            b_search_verbose::<u32>(vec![vec![cursor]], &index.1, 10, 1.0, 1.0, false);
+        assert_eq!(results[0].to_sorted_vec()[0].score, max_score);
+    }
+
+    #[test]
+    fn test_brute_force() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..500 {
+            let num_documents = rng.gen_range(1..300);
+            let num_terms = rng.gen_range(1..12);
+            let bsize = [1, 2, 3, 8, 16, 64, 128][rng.gen_range(0..7)];
+            // A few impacts, so that there are ties, including the smallest and largest ones.
+            let mut documents = vec![Vec::new(); num_documents];
+            for document in documents.iter_mut() {
+                for term_id in 0..num_terms {
+                    if rng.gen_bool(0.3) {
+                        document.push((term_id, [1, 40, 41, 200, 255][rng.gen_range(0..5)]));
+                    }
+                }
+            }
+            let compress_range = rng.gen_bool(0.5);
+            let index = build_index(&documents, bsize, compress_range);
+            let query: Vec<u32> = (0..rng.gen_range(0..6))
+                .map(|_| rng.gen_range(0..num_terms + 1))
+                .collect();
+            let query_text: Vec<_> = query.iter().map(|t| format!("t{}", t)).collect();
+            let k = [1, 3, 10, 11, 150][rng.gen_range(0..5)];
+
+            let query_text = query_text.join(" ");
+            let (results, stats) = search(&index, &query_text, k);
+            // The scores of all the matching documents.
+            let cursors = cursors_from_query_text(&query_text, &index.0);
+            let all: BTreeMap<u32, u16> =
+                exhaustive(&[cursors], &forward_index(&documents), num_documents)[0]
+                    .to_sorted_vec()
+                    .iter()
+                    .map(|e| (e.doc_id.0, e.score))
+                    .collect();
+            let mut expected: Vec<_> = all.values().copied().collect();
+            expected.sort_by_key(|&score| Reverse(score));
+            expected.truncate(k);
+            let scores: Vec<_> = results.iter().map(|&(_, score)| score).collect();
+            assert_eq!(
+                scores, expected,
+                "{:?} {:?} k={} bsize={}",
+                documents, query, k, bsize
+            );
+            for &(doc_id, score) in &results {
+                assert_eq!(all.get(&doc_id), Some(&score));
+            }
+            assert_eq!(stats.no_candidates, expected.is_empty());
+        }
+    }
+
+    const NUM_TERMS: u32 = 10;
+
+    /// Documents made of up to 5 of the first `NUM_TERMS` terms, with impacts drawn from a few
+    /// values half of the time, so that there are ties.
+    fn corpus() -> impl Strategy<Value = Vec<Vec<(u32, u32)>>> {
+        let impact = prop_oneof![1..=255u32, prop::sample::select(vec![1, 40, 41, 200, 255]),];
+        prop::collection::vec(
+            prop::collection::btree_map(0..NUM_TERMS, impact, 0..6)
+                .prop_map(|document| document.into_iter().collect()),
+            1..300,
+        )
+    }
+
+    /// Numbers of results, around the ranks whose impacts are stored in posting lists half of the
+    /// time.
+    fn k() -> impl Strategy<Value = usize> {
+        prop_oneof![
+            1..200usize,
+            prop::sample::select(vec![1, 9, 10, 11, 99, 100, 101, 1000]),
+        ]
+    }
+
+    /// Scores of the top-`k` documents for `query`, mapping terms to weights, returned by
+    /// [`b_search`] and by [`exhaustive`] on the block and plain forward indexes.
+    fn top_k_scores<S: Score>(
+        documents: &[Vec<(u32, u32)>],
+        query: &BTreeMap<u32, u32>,
+        bsize: usize,
+        compress_range: bool,
+        k: usize,
+    ) -> [Vec<S>; 3] {
+        let (index, bfwd) = build_index(documents, bsize, compress_range);
+        let cursors: Vec<_> = query
+            .iter()
+            .filter_map(|(term_id, &weight)| index.get_cursor(&format!("t{}", term_id), weight))
+            .collect();
+        let queries = vec![cursors];
+        let scores = |mut results: Vec<TopKHeap<S>>| -> Vec<S> {
+            results[0].to_sorted_vec().iter().map(|e| e.score).collect()
+        };
+        [
+            scores(b_search_verbose(queries.clone(), &bfwd, k, 1.0, 1.0, false)),
+            scores(exhaustive(&queries, &bfwd, k)),
+            scores(exhaustive(&queries, &forward_index(documents), k)),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(100))]
+
+        // Query weights are small enough for scores not to overflow.
+        #[test]
+        fn prop_same_scores_as_exhaustive(
+            documents in corpus(),
+            query in prop::collection::btree_map(0..NUM_TERMS + 2, 1..=32u32, 0..8),
+            bsize in prop::sample::select(vec![1, 2, 3, 8, 16, 64, 128]),
+            compress_range: bool,
+            k in k(),
+        ) {
+            let [bmp, block, forward] =
+                top_k_scores::<u16>(&documents, &query, bsize, compress_range, k);
+            prop_assert_eq!(&block, &forward);
+            prop_assert_eq!(&bmp, &forward);
+        }
+
+        #[test]
+        fn prop_same_wide_scores_as_exhaustive(
+            documents in corpus(),
+            query in prop::collection::btree_map(0..NUM_TERMS + 2, 1..=5000u32, 0..8),
+            bsize in prop::sample::select(vec![1, 8, 64]),
+            compress_range: bool,
+            k in k(),
+        ) {
+            let [bmp, block, forward] =
+                top_k_scores::<u32>(&documents, &query, bsize, compress_range, k);
+            prop_assert_eq!(&block, &forward);
+            prop_assert_eq!(&bmp, &forward);
+        }
+    }
 }
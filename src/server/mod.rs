@@ -0,0 +1,410 @@
+//! gRPC search service defined in `proto/bmp-search-v1.proto`.
+use anyhow::Result;
+use bytes::{Buf, BufMut};
+use protobuf::Message;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::{Request, Response, Status};
+
+use crate::index::forward_index::BlockForwardIndex;
+use crate::index::inverted_index::Index;
+use crate::index::metadata::DocumentMetadata;
+use crate::index::posting_list::PostingListIterator;
+use crate::query::cursors_from_weighted_query;
+use crate::query::expansion::TermExpansion;
+use crate::query::quantizer::QueryQuantizer;
+use crate::query::score::{needs_wide_scores, Score};
+use crate::search::b_search_verbose;
+
+pub use crate::proto::{ScoredDocument, SearchRequest, SearchResponse};
+
+include!(concat!(env!("OUT_DIR"), "/bmp.SearchService.rs"));
+
+pub use search_service_client::SearchServiceClient;
+pub use search_service_server::{SearchService, SearchServiceServer};
+
+/// Codec (de)serializing the `protobuf` messages generated from the proto files.
+#[derive(Debug)]
+pub struct ProtobufCodec<T, U>(PhantomData<(T, U)>);
+
+impl<T, U> Default for ProtobufCodec<T, U> {
+    fn default() -> Self {
+        ProtobufCodec(PhantomData)
+    }
+}
+
+impl<T: Message, U: Message> Codec for ProtobufCodec<T, U> {
+    type Encode = T;
+    type Decode = U;
+    type Encoder = ProtobufEncoder<T>;
+    type Decoder = ProtobufDecoder<U>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        ProtobufEncoder(PhantomData)
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        ProtobufDecoder(PhantomData)
+    }
+}
+
+#[derive(Debug)]
+pub struct ProtobufEncoder<T>(PhantomData<T>);
+
+impl<T: Message> Encoder for ProtobufEncoder<T> {
+    type Item = T;
+    type Error = Status;
+
+    fn encode(&mut self, item: T, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        let bytes = item
+            .write_to_bytes()
+            .map_err(|e| Status::internal(e.to_string()))?;
+        dst.put_slice(&bytes);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct ProtobufDecoder<U>(PhantomData<U>);
+
+impl<U: Message> Decoder for ProtobufDecoder<U> {
+    type Item = U;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<U>, Status> {
+        let bytes = src.copy_to_bytes(src.remaining());
+        U::parse_from_bytes(&bytes)
+            .map(Some)
+            .map_err(|e| Status::invalid_argument(e.to_string()))
+    }
+}
+
+/// Largest number of term patterns in a query, each of which is matched against the whole term
+/// lexicon.
+const MAX_QUERY_PATTERNS: usize = 16;
+
+/// Largest length in bytes of the regular expressions of a query.
+const MAX_REGEX_LENGTH: usize = 256;
+
+/// Search service answering queries over an index loaded in memory.
+pub struct BmpSearchService {
+    index: Arc<Index>,
+    forward_index: Arc<BlockForwardIndex>,
+    metadata: Option<Arc<DocumentMetadata>>,
+    max_expansions: usize,
+    max_k: Option<usize>,
+}
+
+impl BmpSearchService {
+    pub fn new(index: Index, forward_index: BlockForwardIndex) -> Self {
+        BmpSearchService {
+            index: Arc::new(index),
+            forward_index: Arc::new(forward_index),
+            metadata: None,
+            max_expansions: 0,
+            max_k: None,
+        }
+    }
+
+    /// Sets the document metadata returned with the results that request it.
+    pub fn with_metadata(mut self, metadata: DocumentMetadata) -> Self {
+        self.metadata = Some(Arc::new(metadata));
+        self
+    }
+
+    /// Enables the expansion of query term patterns, into at most `max_expansions` terms each
+    /// whatever the request asks for. Disabled by default, as patterns are costly to match.
+    pub fn with_max_expansions(mut self, max_expansions: usize) -> Self {
+        self.max_expansions = max_expansions;
+        self
+    }
+
+    /// Rejects the requests for more than `max_k` documents. Requests are only limited to the
+    /// number of documents of the index by default.
+    pub fn with_max_k(mut self, max_k: usize) -> Self {
+        self.max_k = Some(max_k);
+        self
+    }
+
+    /// Loads the index stored at `index_path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be read.
+    pub fn from_file<P: Into<PathBuf>>(index_path: P) -> Result<Self> {
+        let mut index = crate::index::BmpIndex::open(index_path)?;
+        let metadata = index.take_metadata();
+        let (index, forward_index) = index.into_parts();
+        let service = Self::new(index, forward_index);
+        Ok(match metadata {
+            Some(metadata) => service.with_metadata(metadata),
+            None => service,
+        })
+    }
+
+    pub fn into_server(self) -> SearchServiceServer<Self> {
+        SearchServiceServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl SearchService for BmpSearchService {
+    async fn search(
+        &self,
+        request: Request<SearchRequest>,
+    ) -> Result<Response<SearchResponse>, Status> {
+        let request = request.into_inner();
+        if request.get_k() == 0 {
+            return Err(Status::invalid_argument("k must be positive"));
+        }
+        if let Some(max_k) = self.max_k.filter(|&max_k| request.get_k() as usize > max_k) {
+            return Err(Status::invalid_argument(format!(
+                "k must be at most {}",
+                max_k
+            )));
+        }
+        let index = Arc::clone(&self.index);
+        let forward_index = Arc::clone(&self.forward_index);
+        let metadata = self.metadata.clone();
+        let max_expansions = match request.get_max_expansions() as usize {
+            0 => 0,
+            _ if self.max_expansions == 0 => {
+                return Err(Status::invalid_argument(
+                    "term expansion is disabled on this server",
+                ))
+            }
+            max_expansions => max_expansions.min(self.max_expansions),
+        };
+        let response = tokio::task::spawn_blocking(move || {
+            search(
+                &index,
+                &forward_index,
+                metadata.as_deref(),
+                max_expansions,
+                &request,
+            )
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        // Only invalid or too many patterns in the query make the search fail.
+        .map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+        Ok(Response::new(response))
+    }
+}
+
+fn search(
+    index: &Index,
+    forward_index: &BlockForwardIndex,
+    metadata: Option<&DocumentMetadata>,
+    max_expansions: usize,
+    request: &SearchRequest,
+) -> Result<SearchResponse> {
+    let mut response = SearchResponse::new();
+    let expanded;
+    let query = match max_expansions {
+        0 => request.get_query(),
+        max_expansions => {
+            expanded = TermExpansion::new(max_expansions)
+                .max_patterns(MAX_QUERY_PATTERNS)
+                .max_regex_length(MAX_REGEX_LENGTH)
+                .expand_query(request.get_query(), index)?;
+            &expanded
+        }
+    };
+    let cursors = cursors_from_weighted_query(query, index, &QueryQuantizer::default());
+    if cursors.is_empty() {
+        return Ok(response);
+    }
+
+    let alpha = match request.get_alpha() {
+        a if a > 0.0 => a,
+        _ => 1.0,
+    };
+    let beta = match request.get_beta() {
+        b if b > 0.0 => b,
+        _ => 1.0,
+    };
+    // The heap of the results is allocated for k documents, at most all of them.
+    let k = (request.get_k() as usize).min(index.num_documents());
+    let results = if needs_wide_scores(&cursors) {
+        top_k::<u32>(cursors, forward_index, k, alpha, beta)
+    } else {
+        top_k::<u16>(cursors, forward_index, k, alpha, beta)
+    };
+    let doc_lexicon = index.documents();
+    for (doc_id, score) in results {
+        let mut doc = ScoredDocument::new();
+        doc.set_docid(doc_lexicon.get(doc_id).unwrap_or_default());
+        doc.set_score(score);
+        if request.get_include_metadata() {
+            if let Some(metadata) = metadata.and_then(|metadata| metadata.get(doc_id)) {
+                doc.set_metadata(metadata.to_string());
+            }
+        }
+        response.mut_results().push(doc);
+    }
+    Ok(response)
+}
+
+/// Ids and scores of the top-k documents, by decreasing score.
+fn top_k<S: Score>(
+    cursors: Vec<PostingListIterator<'_>>,
+    forward_index: &BlockForwardIndex,
+    k: usize,
+    alpha: f32,
+    beta: f32,
+) -> Vec<(u32, f32)> {
+    let mut results = b_search_verbose::<S>(vec![cursors], forward_index, k, alpha, beta, false);
+    results[0]
+        .to_sorted_vec()
+        .iter()
+        .map(|r| (r.doc_id.0, r.score.to_f32()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index::writer::IndexWriter;
+    use tonic::Code;
+
+    fn service() -> BmpSearchService {
+        let mut writer = IndexWriter::new(2);
+        writer.quiet(true);
+        let (cat, cats, dog) = (
+            writer.term_id("cat"),
+            writer.term_id("cats"),
+            writer.term_id("dog"),
+        );
+        writer.add_document("d0", &[(cat, 10)]).unwrap();
+        writer.add_document("d1", &[(cats, 5), (dog, 3)]).unwrap();
+        writer.add_document("d2", &[(dog, 8)]).unwrap();
+        writer.set_metadata(0, r#"{"title": "a cat"}"#).unwrap();
+        let mut index = writer.finish().unwrap();
+        let metadata = index.take_metadata().unwrap();
+        let (index, forward_index) = index.into_parts();
+        BmpSearchService::new(index, forward_index)
+            .with_metadata(metadata)
+            .with_max_expansions(10)
+    }
+
+    fn request(
+        query: &[(&str, f32)],
+        include_metadata: bool,
+        max_expansions: u32,
+    ) -> SearchRequest {
+        let mut request = SearchRequest::new();
+        for &(term, weight) in query {
+            request.mut_query().insert(term.to_string(), weight);
+        }
+        request.set_k(10);
+        request.set_include_metadata(include_metadata);
+        request.set_max_expansions(max_expansions);
+        request
+    }
+
+    fn results(response: &SearchResponse) -> Vec<(&str, &str)> {
+        response
+            .get_results()
+            .iter()
+            .map(|doc| (doc.get_docid(), doc.get_metadata()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let service = service();
+        let search = |request| SearchService::search(&service, Request::new(request));
+
+        let response = search(request(&[("dog", 1.0)], false, 0)).await.unwrap();
+        assert_eq!(results(response.get_ref()), [("d2", ""), ("d1", "")]);
+
+        // Patterns are only expanded when requested.
+        let response = search(request(&[("cat*", 1.0)], false, 0)).await.unwrap();
+        assert!(response.get_ref().get_results().is_empty());
+        let response = search(request(&[("cat*", 1.0)], true, 10)).await.unwrap();
+        assert_eq!(
+            results(response.get_ref()),
+            [("d0", r#"{"title": "a cat"}"#), ("d1", "")]
+        );
+        let response = search(request(&[("cat*", 1.0)], false, 1)).await.unwrap();
+        assert_eq!(results(response.get_ref()), [("d0", "")]);
+        // Requests are limited to the expansions of the server.
+        let response = search(request(&[("/.*/", 1.0)], false, 100)).await.unwrap();
+        assert_eq!(response.get_ref().get_results().len(), 3);
+
+        let mut huge_k = request(&[("dog", 1.0)], false, 0);
+        huge_k.set_k(u32::MAX);
+        let response = search(huge_k.clone()).await.unwrap();
+        assert_eq!(results(response.get_ref()), [("d2", ""), ("d1", "")]);
+        let service_max_k = self::service().with_max_k(100);
+        let status = SearchService::search(&service_max_k, Request::new(huge_k))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let mut zero_k = request(&[("dog", 1.0)], false, 0);
+        zero_k.set_k(0);
+        let status = search(zero_k).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let status = search(request(&[("/c(/", 1.0)], false, 10))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let long_regex = format!("/{}/", "c".repeat(MAX_REGEX_LENGTH + 1));
+        let status = search(request(&[(&long_regex, 1.0)], false, 10))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let patterns: Vec<String> = (0..=MAX_QUERY_PATTERNS)
+            .map(|i| format!("c{}*", i))
+            .collect();
+        let query: Vec<(&str, f32)> = patterns.iter().map(|p| (p.as_str(), 1.0)).collect();
+        let status = search(request(&query, false, 10)).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        // Expansion is disabled by default.
+        let (index, forward_index) = IndexWriter::new(2).finish().unwrap().into_parts();
+        let service = BmpSearchService::new(index, forward_index);
+        let status =
+            SearchService::search(&service, Request::new(request(&[("cat*", 1.0)], false, 10)))
+                .await
+                .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_protobuf_codec() {
+        // A round trip through a server and a client, which (de)serialize the messages with the
+        // codec.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming =
+            tonic::transport::server::TcpIncoming::from_listener(listener, true, None).unwrap();
+        let server = tonic::transport::Server::builder()
+            .add_service(service().into_server())
+            .serve_with_incoming(incoming);
+        tokio::spawn(server);
+
+        let mut client = SearchServiceClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap();
+        let response = client
+            .search(request(&[("cat*", 1.0), ("dog", 0.5)], true, 10))
+            .await
+            .unwrap();
+        let response = response.get_ref();
+        assert_eq!(
+            results(response),
+            [("d0", r#"{"title": "a cat"}"#), ("d1", ""), ("d2", "")]
+        );
+        let scores: Vec<f32> = response
+            .get_results()
+            .iter()
+            .map(|doc| doc.get_score())
+            .collect();
+        assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+}
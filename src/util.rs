@@ -1,3 +1,5 @@
+use crate::index::document_lexicon::DocumentLexicon;
+use crate::query::score::Score;
 use crate::query::topk_heap::TopKHeap;
 
 #[must_use]
@@ -9,11 +11,29 @@ pub fn progress_bar(name: &str, limit: usize) -> indicatif::ProgressBar {
     pb
 }
 
+/// Like [`progress_bar`], but hidden if `quiet`.
+#[must_use]
+pub fn quiet_progress_bar(name: &str, limit: usize, quiet: bool) -> indicatif::ProgressBar {
+    if quiet {
+        indicatif::ProgressBar::hidden()
+    } else {
+        progress_bar(name, limit)
+    }
+}
+
+/// Initializes the logging of the binaries to stderr, at the `info` level, or `warn` if `quiet`,
+/// unless overridden by the `RUST_LOG` environment variable.
+#[cfg(feature = "cli")]
+pub fn init_logger(quiet: bool) {
+    let level = if quiet { "warn" } else { "info" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level)).init();
+}
+
 // Function to convert query results to TREC format and print to stdout
-pub fn to_trec(
+pub fn to_trec<S: Score>(
     query_ids: &[String],
-    mut results: Vec<TopKHeap<u16>>,
-    doc_lexicon: &[String],
+    mut results: Vec<TopKHeap<S>>,
+    doc_lexicon: &DocumentLexicon,
 ) -> String {
     let mut output = String::new();
 
@@ -22,7 +42,7 @@ pub fn to_trec(
             let line = format!(
                 "{} Q0 {} {} {} BMP\n",
                 id,
-                doc_lexicon[r.doc_id.0 as usize],
+                doc_lexicon.get(r.doc_id.0).unwrap_or_default(),
                 rank + 1,
                 r.score,
             );